@@ -0,0 +1,44 @@
+/*
+Host side of the audio pipeline. The emulator produces samples through
+`APU::take_samples`, which the frontend hands to one of the sinks below.
+*/
+
+pub mod ring_buffer;
+#[cfg(feature = "device-audio")]
+pub mod device;
+pub mod file;
+pub mod wav;
+
+#[cfg(feature = "device-audio")]
+pub use device::DeviceSink;
+pub use file::{FileSink, NullSink};
+
+use crate::system::apu::resampler::MAX_RATE_ADJUST;
+
+pub trait AudioSink {
+    fn sample_rate(&self) -> u32;
+    fn write(&mut self, samples: &[f32]) -> anyhow::Result<()>;
+
+    /// Samples queued for playback, `None` for sinks that consume samples immediately
+    fn buffered(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// Keeps the amount of buffered audio near a target level, by nudging the emulated
+/// sample rate up when the buffer is draining and down when it is filling up
+pub struct RateControl {
+    target: usize,
+}
+
+impl RateControl {
+    pub fn new(target: usize) -> Self {
+        Self { target }
+    }
+
+    /// The rate is adjusted the most when the buffer is empty or twice the target
+    pub fn ratio(&self, buffered: usize) -> f64 {
+        let fill = buffered as f64 / self.target as f64;
+        1.0 - ((fill - 1.0) * MAX_RATE_ADJUST).clamp(-MAX_RATE_ADJUST, MAX_RATE_ADJUST)
+    }
+}
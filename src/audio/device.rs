@@ -0,0 +1,110 @@
+use std::{sync::Arc, thread, time::{Duration, Instant}};
+
+use anyhow::format_err;
+use cpal::{traits::{DeviceTrait, HostTrait, StreamTrait}, Sample, SampleFormat, SampleRate, Stream, StreamConfig};
+
+use super::{ring_buffer::RingBuffer, AudioSink};
+
+/// Plays samples on the default output device of the host
+pub struct DeviceSink {
+    sample_rate: u32,
+    buffer: Arc<RingBuffer>,
+    // The stream stops playing when dropped
+    _stream: Stream,
+}
+
+impl DeviceSink {
+    /// Longest time `write` waits for the output thread to make room in the buffer
+    const WRITE_TIMEOUT: Duration = Duration::from_millis(100);
+
+    /// Opens the default output device, trying to use the requested sample rate
+    pub fn open(sample_rate: u32) -> anyhow::Result<Self> {
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or_else(|| format_err!("no audio output device available"))?;
+
+        let supported = device.supported_output_configs()?
+            .find(|c| c.min_sample_rate().0 <= sample_rate && c.max_sample_rate().0 >= sample_rate)
+            .map(|c| c.with_sample_rate(SampleRate(sample_rate)));
+        let supported = match supported {
+            Some(supported) => supported,
+            None => device.default_output_config()?,
+        };
+
+        let format = supported.sample_format();
+        let config: StreamConfig = supported.into();
+        let sample_rate = config.sample_rate.0;
+
+        // An eighth of a second of audio
+        let buffer = Arc::new(RingBuffer::new(sample_rate as usize / 8));
+
+        let stream = match format {
+            SampleFormat::F32 => build_stream::<f32>(&device, &config, buffer.clone())?,
+            SampleFormat::I16 => build_stream::<i16>(&device, &config, buffer.clone())?,
+            SampleFormat::U16 => build_stream::<u16>(&device, &config, buffer.clone())?,
+        };
+        stream.play()?;
+
+        Ok(Self { sample_rate, buffer, _stream: stream })
+    }
+}
+
+fn build_stream<T: Sample>(device: &cpal::Device, config: &StreamConfig, buffer: Arc<RingBuffer>) -> anyhow::Result<Stream> {
+    let channels = config.channels as usize;
+    let mut mono = Vec::new();
+    let mut last = 0.0;
+
+    let stream = device.build_output_stream(
+        config,
+        move |data: &mut [T], _| {
+            let frames = data.len() / channels;
+            mono.resize(frames, 0.0);
+
+            // On underrun, hold the last sample rather than dropping to zero, to avoid clicks
+            let available = buffer.pop_into(&mut mono);
+            if available > 0 {
+                last = mono[available - 1];
+            }
+            for sample in &mut mono[available..] {
+                *sample = last;
+            }
+
+            for (frame, sample) in data.chunks_mut(channels).zip(&mono) {
+                let value = T::from(sample);
+                for out in frame {
+                    *out = value;
+                }
+            }
+        },
+        |err| eprintln!("Audio stream error: {err}"),
+    )?;
+    Ok(stream)
+}
+
+impl AudioSink for DeviceSink {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn write(&mut self, samples: &[f32]) -> anyhow::Result<()> {
+        // Block while the buffer is full, which also keeps the emulation from running ahead
+        let started = Instant::now();
+        let mut remaining = samples;
+        while !remaining.is_empty() {
+            let queued = self.buffer.push(remaining);
+            remaining = &remaining[queued..];
+            if !remaining.is_empty() {
+                if started.elapsed() > Self::WRITE_TIMEOUT {
+                    // The device is not consuming samples, drop the rest
+                    break;
+                }
+                thread::sleep(Duration::from_millis(1));
+            }
+        }
+        Ok(())
+    }
+
+    fn buffered(&self) -> Option<usize> {
+        Some(self.buffer.len())
+    }
+}
@@ -0,0 +1,54 @@
+use std::{fs, io::{BufWriter, Write}, path::Path};
+
+use super::AudioSink;
+
+/// Discards all samples, for running without any audio output
+pub struct NullSink {
+    sample_rate: u32,
+}
+
+impl NullSink {
+    pub fn new(sample_rate: u32) -> Self {
+        Self { sample_rate }
+    }
+}
+
+impl AudioSink for NullSink {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn write(&mut self, _samples: &[f32]) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes raw mono 16-bit little-endian PCM, e.g. for `aplay -f S16_LE -r <rate> <file>`
+pub struct FileSink {
+    sample_rate: u32,
+    writer: BufWriter<fs::File>,
+}
+
+impl FileSink {
+    pub fn create<P: AsRef<Path>>(path: P, sample_rate: u32) -> anyhow::Result<Self> {
+        let writer = BufWriter::new(fs::File::create(path)?);
+        Ok(Self { sample_rate, writer })
+    }
+}
+
+impl AudioSink for FileSink {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn write(&mut self, samples: &[f32]) -> anyhow::Result<()> {
+        for sample in samples {
+            self.writer.write_all(&to_i16(*sample).to_le_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn to_i16(sample: f32) -> i16 {
+    (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16
+}
@@ -0,0 +1,63 @@
+use std::{collections::VecDeque, sync::Mutex};
+
+/// Fixed size sample queue shared between the emulation and the audio output thread
+pub struct RingBuffer {
+    samples: Mutex<VecDeque<f32>>,
+    capacity: usize,
+}
+
+impl RingBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            samples: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Queues as many samples as there is room for, returning how many were queued
+    pub fn push(&self, samples: &[f32]) -> usize {
+        let mut queue = self.samples.lock().unwrap();
+        let count = samples.len().min(self.capacity - queue.len());
+        queue.extend(&samples[..count]);
+        count
+    }
+
+    /// Fills `out` with queued samples, returning how many were available
+    pub fn pop_into(&self, out: &mut [f32]) -> usize {
+        let mut queue = self.samples.lock().unwrap();
+        let count = out.len().min(queue.len());
+        for (slot, sample) in out.iter_mut().zip(queue.drain(..count)) {
+            *slot = sample;
+        }
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RingBuffer;
+
+    #[test]
+    fn push_and_pop_respect_capacity() {
+        let buffer = RingBuffer::new(4);
+        assert_eq!(buffer.push(&[1.0, 2.0, 3.0]), 3);
+        assert_eq!(buffer.push(&[4.0, 5.0]), 1);
+
+        let mut out = [0.0; 6];
+        assert_eq!(buffer.pop_into(&mut out), 4);
+        assert_eq!(out, [1.0, 2.0, 3.0, 4.0, 0.0, 0.0]);
+        assert!(buffer.is_empty());
+    }
+}
@@ -0,0 +1,116 @@
+use std::{fs, io::{BufWriter, Seek, SeekFrom, Write}, path::Path};
+
+use super::file::to_i16;
+
+/// Writes mono 16-bit PCM WAV files. The header sizes are filled in by
+/// `finish`, or when the writer is dropped, so the file stays playable if
+/// the emulator bails out early.
+pub struct WavWriter {
+    writer: BufWriter<fs::File>,
+    data_len: u32,
+    finished: bool,
+}
+
+impl WavWriter {
+    const CHANNELS: u16 = 1;
+    const BITS_PER_SAMPLE: u16 = 16;
+
+    pub fn create<P: AsRef<Path>>(path: P, sample_rate: u32) -> anyhow::Result<Self> {
+        let mut writer = BufWriter::new(fs::File::create(path)?);
+
+        let block_align = Self::CHANNELS * Self::BITS_PER_SAMPLE / 8;
+        let byte_rate = sample_rate * block_align as u32;
+
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&0u32.to_le_bytes())?; // patched in finish
+        writer.write_all(b"WAVE")?;
+
+        writer.write_all(b"fmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        writer.write_all(&1u16.to_le_bytes())?; // PCM
+        writer.write_all(&Self::CHANNELS.to_le_bytes())?;
+        writer.write_all(&sample_rate.to_le_bytes())?;
+        writer.write_all(&byte_rate.to_le_bytes())?;
+        writer.write_all(&block_align.to_le_bytes())?;
+        writer.write_all(&Self::BITS_PER_SAMPLE.to_le_bytes())?;
+
+        writer.write_all(b"data")?;
+        writer.write_all(&0u32.to_le_bytes())?; // patched in finish
+
+        Ok(Self { writer, data_len: 0, finished: false })
+    }
+
+    pub fn write_sample(&mut self, sample: f32) -> anyhow::Result<()> {
+        self.writer.write_all(&to_i16(sample).to_le_bytes())?;
+        self.data_len += 2;
+        Ok(())
+    }
+
+    /// Fills in the chunk sizes in the header and flushes the file
+    pub fn finish(mut self) -> anyhow::Result<()> {
+        self.finished = true;
+        self.patch_header()
+    }
+
+    fn patch_header(&mut self) -> anyhow::Result<()> {
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer.write_all(&(36 + self.data_len).to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(40))?;
+        self.writer.write_all(&self.data_len.to_le_bytes())?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+impl Drop for WavWriter {
+    fn drop(&mut self) {
+        if !self.finished {
+            // nowhere to report the error, the file just keeps its empty sizes
+            let _ = self.patch_header();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use super::WavWriter;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("robust-{}-{name}.wav", std::process::id()))
+    }
+
+    fn chunk_sizes(path: &PathBuf) -> (u32, u32) {
+        let bytes = fs::read(path).unwrap();
+        let word = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        (word(4), word(40))
+    }
+
+    #[test]
+    fn finish_fills_in_the_chunk_sizes() {
+        let path = temp_path("finish");
+        let mut wav = WavWriter::create(&path, 44100).unwrap();
+        for _ in 0..3 {
+            wav.write_sample(0.5).unwrap();
+        }
+        wav.finish().unwrap();
+
+        assert_eq!(fs::metadata(&path).unwrap().len(), 44 + 6);
+        assert_eq!(chunk_sizes(&path), (36 + 6, 6));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn dropping_fills_in_the_chunk_sizes() {
+        let path = temp_path("drop");
+        let mut wav = WavWriter::create(&path, 44100).unwrap();
+        for _ in 0..5 {
+            wav.write_sample(-0.5).unwrap();
+        }
+        drop(wav);
+
+        assert_eq!(chunk_sizes(&path), (36 + 10, 10));
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -1,5 +1,8 @@
 use clap::{Parser, builder::{PathBufValueParser, TypedValueParser, PossibleValuesParser}};
-use robust::{system::{self, apu::ControllerButton, options::Options, addr::Addr}, font::Font, clapx::{ensure_existing_file, scale_value_parser, SCALE_VALUES}, screen::Screen};
+use robust::{system::{self, apu::ControllerButton, options::Options, addr::Addr, ppu::palette::{self, NtscPalette}}, font::Font, clapx::{ensure_existing_file, scale_value_parser, SCALE_VALUES, AUDIO_SINK_VALUES, SAMPLE_RATE_VALUES, sample_rate_value_parser}, screen::Screen};
+use robust::audio::{AudioSink, FileSink, NullSink, RateControl};
+#[cfg(feature = "device-audio")]
+use robust::audio::DeviceSink;
 
 use std::{env, fs, path::{PathBuf}};
 use anyhow::Result;
@@ -23,6 +26,61 @@ pub struct Args {
 
     #[arg(short = 'H', long = "history", default_value = "10")]
     history: usize,
+
+    #[arg(long = "audio", value_parser = PossibleValuesParser::new(AUDIO_SINK_VALUES), default_value = "device")]
+    audio: String,
+
+    /// Raw 16-bit PCM output path, used with `--audio file`
+    #[arg(long = "audio-file", default_value = "audio.raw")]
+    audio_file: PathBuf,
+
+    #[arg(long = "sample-rate", value_parser = PossibleValuesParser::new(SAMPLE_RATE_VALUES).try_map(sample_rate_value_parser), default_value = "44100")]
+    sample_rate: u32,
+
+    /// Record the mixed audio output to a WAV file
+    #[arg(long = "record-audio")]
+    record_audio: Option<PathBuf>,
+
+    /// Also record a WAV file per channel, and one for the cartridge's expansion
+    /// audio, next to the `--record-audio` file
+    #[arg(long = "record-stems", default_value = "false", requires = "record_audio")]
+    record_stems: bool,
+
+    /// Draw every sprite on a line instead of the hardware's 8, which removes flicker
+    #[arg(long = "no-sprite-limit", default_value = "false")]
+    no_sprite_limit: bool,
+
+    /// Load the colors from a 192 or 1536 byte `.pal` file
+    #[arg(long = "palette", value_parser = PathBufValueParser::new().try_map(ensure_existing_file), conflicts_with = "ntsc")]
+    palette: Option<PathBuf>,
+
+    /// Generate the colors by decoding the NTSC signal, tuned with the options below
+    #[arg(long = "ntsc", default_value = "false")]
+    ntsc: bool,
+
+    /// Hue rotation of the generated palette, in degrees
+    #[arg(long = "hue", default_value = "0", requires = "ntsc", allow_hyphen_values = true)]
+    hue: f32,
+
+    /// Saturation multiplier of the generated palette
+    #[arg(long = "saturation", default_value = "1", requires = "ntsc")]
+    saturation: f32,
+
+    /// Contrast multiplier of the generated palette
+    #[arg(long = "contrast", default_value = "1", requires = "ntsc")]
+    contrast: f32,
+
+    /// Brightness offset of the generated palette, added to the decoded luma
+    #[arg(long = "brightness", default_value = "0", requires = "ntsc", allow_hyphen_values = true)]
+    brightness: f32,
+
+    /// Display gamma, 2.2 leaves the decoded colors unchanged
+    #[arg(long = "gamma", default_value = "2.2", requires = "ntsc")]
+    gamma: f32,
+
+    /// Write the colors in use to a 1536 byte `.pal` file
+    #[arg(long = "save-palette")]
+    save_palette: Option<PathBuf>,
 }
 
 fn main() -> Result<()> { 
@@ -34,9 +92,15 @@ fn main() -> Result<()> {
 
     
     color_backtrace::install();
+
+    let mut audio_sink = open_audio_sink(&args)?;
+    let rate_control = RateControl::new(audio_sink.sample_rate() as usize / 16);
+
     let mut system = system::System::new(Options{
         dump_ops: args.trace,
         history_len: args.history,
+        sample_rate: Some(audio_sink.sample_rate()),
+        sprite_limit: !args.no_sprite_limit,
         ..Default::default()
     })?;
 
@@ -90,6 +154,29 @@ fn main() -> Result<()> {
         }
     }
 
+    if let Some(record_audio) = &args.record_audio {
+        system.record_audio(record_audio, args.sample_rate, args.record_stems)?;
+        eprintln!("Recording audio to {}", record_audio.to_string_lossy());
+    }
+
+    if let Some(path) = &args.palette {
+        system.set_palette(palette::load(path)?)?;
+    } else if args.ntsc {
+        let ntsc = NtscPalette {
+            hue: args.hue,
+            saturation: args.saturation,
+            contrast: args.contrast,
+            brightness: args.brightness,
+            gamma: args.gamma,
+        };
+        system.set_palette(ntsc.generate())?;
+    }
+
+    if let Some(path) = &args.save_palette {
+        palette::save(path, system.palette())?;
+        eprintln!("Saved palette to {}", path.to_string_lossy());
+    }
+
     system.reset()?;
 
     eprintln!();
@@ -104,6 +191,7 @@ fn main() -> Result<()> {
     window.limit_update_rate(Some(std::time::Duration::from_micros(8300)));
 
     let mut last_frame = std::time::Instant::now();
+    let mut jam_reported = false;
 
     while window.is_open() && !window.is_key_down(Key::Escape) {
 
@@ -115,16 +203,6 @@ fn main() -> Result<()> {
             }
         }
 
-        if window.is_key_pressed(Key::F1, KeyRepeat::No) {
-            system.offset -= 1;
-            eprintln!("Offset decreased to {}", system.offset);
-        }
-
-        if window.is_key_pressed(Key::F2, KeyRepeat::No) {
-            system.offset += 1;
-            eprintln!("Offset increased to {}", system.offset);
-        }
-
         if window.is_key_pressed(Key::F3, KeyRepeat::No) {
             paused.toggle();
         }
@@ -171,6 +249,11 @@ fn main() -> Result<()> {
                 }
             }
 
+            audio_sink.write(&system.apu.take_samples())?;
+            if let Some(buffered) = audio_sink.buffered() {
+                system.apu.set_rate_adjust(rate_control.ratio(buffered));
+            }
+
             Some(last_state)
         } else {
             None
@@ -207,6 +290,16 @@ fn main() -> Result<()> {
             }
         }
 
+        if let Some(addr) = system.jammed_at() {
+            if !jam_reported {
+                eprintln!("CPU jammed at ${addr:04X}");
+                eprintln!("\nLast instructions before the jam:");
+                system.dump_history();
+                jam_reported = true;
+            }
+            screen.draw_text(&font, 10, HEIGHT / 2, &format!("CPU jammed at ${addr:04X}"), 1)?;
+        }
+
         if debug_opts.show_test_regs() {
             let test_regs_text = format!("{:02x} {:02x}", system.peek_byte(2), system.peek_byte(3));
             screen.draw_text(&font, WIDTH-64, 16, &test_regs_text, 1)?;
@@ -237,11 +330,34 @@ fn main() -> Result<()> {
         eprintln!("\nStack:"); system.dump_stack();
     }
 
+    system.stop_recording()?;
+
     eprintln!("\nDone!");
 
     Ok(())
 }
 
+fn open_audio_sink(args: &Args) -> Result<Box<dyn AudioSink>> {
+    match args.audio.as_str() {
+        #[cfg(feature = "device-audio")]
+        "device" => match DeviceSink::open(args.sample_rate) {
+            Ok(sink) => Ok(Box::new(sink)),
+            Err(e) => {
+                eprintln!("Failed to open audio device, running without sound: {e}");
+                Ok(Box::new(NullSink::new(args.sample_rate)))
+            }
+        },
+        #[cfg(not(feature = "device-audio"))]
+        "device" => {
+            eprintln!("Built without the device-audio feature, running without sound");
+            Ok(Box::new(NullSink::new(args.sample_rate)))
+        }
+        "file" => Ok(Box::new(FileSink::create(&args.audio_file, args.sample_rate)?)),
+        "null" => Ok(Box::new(NullSink::new(args.sample_rate))),
+        _ => unreachable!("invalid value should be caught by PossibleValuesParser")
+    }
+}
+
 fn map_key_to_button(key: Key) -> Option<(usize, ControllerButton)> {
     match key {
         Key::Up        => Some((0, ControllerButton::Up)),
@@ -40,6 +40,21 @@ pub fn scale_value_parser(value: String) -> anyhow::Result<Scale> {
     }
 }
 
+pub static AUDIO_SINK_VALUES: [&str; 3] = [
+    "device",
+    "file",
+    "null",
+];
+
+pub static SAMPLE_RATE_VALUES: [&str; 2] = [
+    "44100",
+    "48000",
+];
+
+pub fn sample_rate_value_parser(value: String) -> anyhow::Result<u32> {
+    Ok(value.parse()?)
+}
+
 // #[derive(Clone)]
 // struct ExistingFileValueParser;
 
@@ -5,6 +5,7 @@ pub mod font;
 pub mod clapx;
 pub mod screen;
 pub mod mappers;
+pub mod audio;
 
 #[cfg(test)]
 mod tests;
\ No newline at end of file
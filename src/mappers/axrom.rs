@@ -0,0 +1,68 @@
+use std::fmt::Display;
+
+use crate::system::{addr::Addr, bus::open_bus, cart::Header};
+
+use super::{bus_conflict, Mapper, Mirroring};
+
+
+// INES 07
+pub struct AxROM {
+    pub(crate) prg_rom: Vec<u8>,
+    pub(crate) chr_ram: Vec<u8>,
+    prg_bank: u8,
+    upper_nametable: bool,
+    bus_conflicts: bool,
+}
+impl AxROM {
+    pub fn new(header: &Header, prg_rom: Vec<u8>, _chr_rom: Vec<u8>) -> Self {
+        Self {
+            prg_rom,
+            chr_ram: vec![0u8; 8192],
+            prg_bank: 0,
+            upper_nametable: false,
+            bus_conflicts: header.bus_conflicts,
+        }
+    }
+}
+
+impl Mapper for AxROM {
+    fn ppu_write(&mut self, addr: Addr, value: u8) -> anyhow::Result<()> {
+        self.chr_ram[addr.0 as usize] = value;
+        Ok(())
+    }
+
+    fn cpu_write(&mut self, addr: Addr, value: u8) -> anyhow::Result<()> {
+        if addr >= 0x8000 {
+            let value = bus_conflict(self, addr, value, self.bus_conflicts)?;
+            self.prg_bank = value & 0b0000_0111;
+            self.upper_nametable = value & 0b0001_0000 != 0;
+        }
+        Ok(())
+    }
+
+    fn ppu_peek(&self, addr: Addr) -> anyhow::Result<u8> {
+        Ok(self.chr_ram[addr.0 as usize])
+    }
+
+    fn cpu_read(&self, addr: Addr) -> anyhow::Result<u8> {
+        if addr < 0x6000 {
+            anyhow::bail!("read outside pgm range: {addr}")
+        } else if addr < 0x8000 {
+            // nothing is mapped to $6000-$7FFF on this board
+            Ok(open_bus(addr))
+        } else {
+            let rom_addr = self.prg_bank as usize * 0x8000 + (addr.0 as usize - 0x8000);
+            Ok(self.prg_rom[rom_addr % self.prg_rom.len()])
+        }
+    }
+
+    fn mirroring(&self) -> Option<Mirroring> {
+        Some(if self.upper_nametable {Mirroring::OneScreenUpper} else {Mirroring::OneScreenLower})
+    }
+}
+
+impl Display for AxROM {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("AxROM")
+    }
+}
@@ -0,0 +1,60 @@
+use std::fmt::Display;
+
+use crate::system::{addr::Addr, bus::open_bus, cart::Header};
+
+use super::{bus_conflict, Mapper};
+
+
+// INES 34 with CHR RAM
+pub struct BNROM {
+    pub(crate) prg_rom: Vec<u8>,
+    pub(crate) chr_ram: Vec<u8>,
+    prg_bank: u8,
+    bus_conflicts: bool,
+}
+impl BNROM {
+    pub fn new(header: &Header, prg_rom: Vec<u8>, _chr_rom: Vec<u8>) -> Self {
+        Self {
+            prg_rom,
+            chr_ram: vec![0u8; 8192],
+            prg_bank: 0,
+            bus_conflicts: header.bus_conflicts,
+        }
+    }
+}
+
+impl Mapper for BNROM {
+    fn ppu_write(&mut self, addr: Addr, value: u8) -> anyhow::Result<()> {
+        self.chr_ram[addr.0 as usize] = value;
+        Ok(())
+    }
+
+    fn cpu_write(&mut self, addr: Addr, value: u8) -> anyhow::Result<()> {
+        if addr >= 0x8000 {
+            self.prg_bank = bus_conflict(self, addr, value, self.bus_conflicts)?;
+        }
+        Ok(())
+    }
+
+    fn ppu_peek(&self, addr: Addr) -> anyhow::Result<u8> {
+        Ok(self.chr_ram[addr.0 as usize])
+    }
+
+    fn cpu_read(&self, addr: Addr) -> anyhow::Result<u8> {
+        if addr < 0x6000 {
+            anyhow::bail!("read outside pgm range: {addr}")
+        } else if addr < 0x8000 {
+            // nothing is mapped to $6000-$7FFF on this board
+            Ok(open_bus(addr))
+        } else {
+            let rom_addr = self.prg_bank as usize * 0x8000 + (addr.0 as usize - 0x8000);
+            Ok(self.prg_rom[rom_addr % self.prg_rom.len()])
+        }
+    }
+}
+
+impl Display for BNROM {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("BNROM")
+    }
+}
@@ -0,0 +1,65 @@
+use std::fmt::Display;
+
+use crate::system::{addr::Addr, bus::open_bus, cart::Header};
+
+use super::{bus_conflict, Mapper};
+
+
+// INES 11
+pub struct ColorDreams {
+    pub(crate) prg_rom: Vec<u8>,
+    pub(crate) chr_rom: Vec<u8>,
+    prg_bank: u8,
+    chr_bank: u8,
+    bus_conflicts: bool,
+}
+impl ColorDreams {
+    pub fn new(header: &Header, prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Self {
+        Self {
+            prg_rom,
+            chr_rom,
+            prg_bank: 0,
+            chr_bank: 0,
+            bus_conflicts: header.bus_conflicts,
+        }
+    }
+}
+
+impl Mapper for ColorDreams {
+    fn ppu_write(&mut self, _addr: Addr, _value: u8) -> anyhow::Result<()> {
+        // CHR ROM
+        Ok(())
+    }
+
+    fn cpu_write(&mut self, addr: Addr, value: u8) -> anyhow::Result<()> {
+        if addr >= 0x8000 {
+            let value = bus_conflict(self, addr, value, self.bus_conflicts)?;
+            self.prg_bank = value & 0b0000_0011;
+            self.chr_bank = value >> 4;
+        }
+        Ok(())
+    }
+
+    fn ppu_peek(&self, addr: Addr) -> anyhow::Result<u8> {
+        let chr_addr = self.chr_bank as usize * 0x2000 + addr.0 as usize;
+        Ok(self.chr_rom[chr_addr % self.chr_rom.len()])
+    }
+
+    fn cpu_read(&self, addr: Addr) -> anyhow::Result<u8> {
+        if addr < 0x6000 {
+            anyhow::bail!("read outside pgm range: {addr}")
+        } else if addr < 0x8000 {
+            // nothing is mapped to $6000-$7FFF on this board
+            Ok(open_bus(addr))
+        } else {
+            let rom_addr = self.prg_bank as usize * 0x8000 + (addr.0 as usize - 0x8000);
+            Ok(self.prg_rom[rom_addr % self.prg_rom.len()])
+        }
+    }
+}
+
+impl Display for ColorDreams {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Color Dreams")
+    }
+}
@@ -108,7 +108,7 @@ impl Mapper for MMC1 {
         Ok(())
     }
 
-    fn ppu_read(&self, addr: Addr) -> anyhow::Result<u8> {
+    fn ppu_peek(&self, addr: Addr) -> anyhow::Result<u8> {
  
         if self.chr_dual_bank() {
             // eprintln!("DUAL READ!");
@@ -0,0 +1,199 @@
+use std::fmt::Display;
+
+use crate::system::{addr::Addr, cart::Header};
+
+use super::{Mapper, Mirroring};
+
+
+// INES 09 (MMC2) and 10 (MMC4)
+pub struct MMC2 {
+    pub(crate) prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    pub(crate) chr_rom: Vec<u8>,
+    /// MMC4 switches 16 KB of PRG instead of 8 KB
+    mmc4: bool,
+    prg_bank: u8,
+    /// 4 KB CHR banks for each pattern table, picked by its latch
+    chr_banks: [[u8; 2]; 2],
+    /// Which of the two banks each pattern table uses, set by fetching tile $FD or $FE
+    latches: [usize; 2],
+    horizontal_mirroring: bool,
+}
+
+impl MMC2 {
+    pub fn new(header: &Header, prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Self {
+        Self {
+            prg_rom,
+            prg_ram: vec![0; 0x2000],
+            chr_rom,
+            mmc4: header.mapper_id == 10,
+            prg_bank: 0,
+            chr_banks: [[0; 2]; 2],
+            latches: [1; 2],
+            horizontal_mirroring: !header.vertical_mirroring,
+        }
+    }
+
+    fn prg_addr(&self, addr: Addr) -> usize {
+        let offset = addr.0 as usize - 0x8000;
+        let rom_addr = if self.mmc4 {
+            // 16 KB switchable, last 16 KB fixed
+            match offset / 0x4000 {
+                0 => self.prg_bank as usize * 0x4000 + offset,
+                _ => self.prg_rom.len() - 0x8000 + offset,
+            }
+        } else {
+            // 8 KB switchable, last three 8 KB fixed
+            match offset / 0x2000 {
+                0 => self.prg_bank as usize * 0x2000 + offset,
+                _ => self.prg_rom.len() - 0x8000 + offset,
+            }
+        };
+        rom_addr % self.prg_rom.len()
+    }
+}
+
+impl Mapper for MMC2 {
+    fn ppu_write(&mut self, _addr: Addr, _value: u8) -> anyhow::Result<()> {
+        // CHR ROM
+        Ok(())
+    }
+
+    fn cpu_write(&mut self, addr: Addr, value: u8) -> anyhow::Result<()> {
+        if addr < 0x6000 {
+            anyhow::bail!("tried to write 0x{value:02x} outside pgm range: {addr}")
+        } else if addr < 0x8000 {
+            self.prg_ram[addr.0 as usize - 0x6000] = value;
+            return Ok(())
+        }
+
+        let value = value & 0b0001_1111;
+        match addr.0 & 0xf000 {
+            0xa000 => self.prg_bank = value & 0x0f,
+            0xb000 => self.chr_banks[0][0] = value,
+            0xc000 => self.chr_banks[0][1] = value,
+            0xd000 => self.chr_banks[1][0] = value,
+            0xe000 => self.chr_banks[1][1] = value,
+            0xf000 => self.horizontal_mirroring = value & 1 != 0,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn ppu_peek(&self, addr: Addr) -> anyhow::Result<u8> {
+        let table = addr.0 as usize / 0x1000;
+        let bank = self.chr_banks[table][self.latches[table]] as usize;
+        let chr_addr = bank * 0x1000 + (addr.0 as usize % 0x1000);
+        Ok(self.chr_rom[chr_addr % self.chr_rom.len()])
+    }
+
+    fn ppu_read(&mut self, addr: Addr) -> anyhow::Result<u8> {
+        let value = self.ppu_peek(addr)?;
+
+        // The latch flips after the fetch, so the tile itself still uses the
+        // old bank. MMC2 only looks at the first byte for the left table.
+        let table = addr.0 as usize / 0x1000;
+        let exact = table == 0 && !self.mmc4;
+        match addr.0 & 0x0ff8 {
+            0x0fd8 if !exact || addr.0 == 0x0fd8 => self.latches[table] = 0,
+            0x0fe8 if !exact || addr.0 == 0x0fe8 => self.latches[table] = 1,
+            _ => {}
+        }
+        Ok(value)
+    }
+
+    fn cpu_read(&self, addr: Addr) -> anyhow::Result<u8> {
+        if addr < 0x6000 {
+            anyhow::bail!("read outside pgm range: {addr}")
+        } else if addr < 0x8000 {
+            Ok(self.prg_ram[addr.0 as usize - 0x6000])
+        } else {
+            Ok(self.prg_rom[self.prg_addr(addr)])
+        }
+    }
+
+    fn mirroring(&self) -> Option<Mirroring> {
+        Some(if self.horizontal_mirroring {Mirroring::Horizontal} else {Mirroring::Vertical})
+    }
+}
+
+impl Display for MMC2 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(if self.mmc4 {"MMC4"} else {"MMC2"})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{mappers::Mapper, system::{addr::Addr, cart::Header}};
+
+    use super::MMC2;
+
+    fn mmc2(mapper_id: u16) -> MMC2 {
+        // every 8 KB PRG bank and 4 KB CHR bank is filled with its own number
+        let prg_rom = (0..16).flat_map(|bank| [bank; 0x2000]).collect();
+        let chr_rom = (0..8).flat_map(|bank| [bank; 0x1000]).collect();
+        let mut mapper = MMC2::new(&Header::for_tests(mapper_id, 0x20000, 0x8000), prg_rom, chr_rom);
+        for (addr, bank) in [(0xb000, 0), (0xc000, 1), (0xd000, 2), (0xe000, 3)] {
+            mapper.cpu_write(Addr(addr), bank).unwrap();
+        }
+        mapper
+    }
+
+    /// Fetches each address and checks the bank it came from and the bank
+    /// the same table uses afterwards
+    fn assert_fetches(mapper: &mut MMC2, fetches: &[(u16, u8, u8)]) {
+        for &(addr, before, after) in fetches {
+            assert_eq!(mapper.ppu_read(Addr(addr)).unwrap(), before, "fetching {addr:04x}");
+            assert_eq!(mapper.ppu_peek(Addr(addr & 0x1000)).unwrap(), after, "after fetching {addr:04x}");
+        }
+    }
+
+    #[test]
+    fn mmc2_left_latch_needs_the_exact_address() {
+        let mut mapper = mmc2(9);
+        assert_fetches(&mut mapper, &[
+            (0x0fd9, 1, 1),
+            (0x0fd8, 1, 0),
+            (0x0fe9, 0, 0),
+            (0x0fe8, 0, 1),
+        ]);
+    }
+
+    #[test]
+    fn mmc4_left_latch_takes_the_whole_tile_row() {
+        let mut mapper = mmc2(10);
+        assert_fetches(&mut mapper, &[
+            (0x0fdf, 1, 0),
+            (0x0fe8, 0, 1),
+            (0x0fd8, 1, 0),
+            (0x0fef, 0, 1),
+        ]);
+    }
+
+    #[test]
+    fn right_latch_takes_the_whole_tile_row() {
+        for mapper_id in [9, 10] {
+            let mut mapper = mmc2(mapper_id);
+            let fetches: Vec<_> = (0x1fd8..=0x1fdf)
+                .flat_map(|addr| [(addr, 3, 2), (0x1fe8, 2, 3)])
+                .collect();
+            assert_fetches(&mut mapper, &fetches);
+            // the left table is untouched
+            assert_eq!(mapper.ppu_peek(Addr(0x0000)).unwrap(), 1);
+        }
+    }
+
+    #[test]
+    fn prg_bank_sizes() {
+        let prg = |mapper: &MMC2| [0x8000, 0xa000, 0xc000, 0xe000].map(|addr| mapper.cpu_read(Addr(addr)).unwrap());
+
+        let mut mapper = mmc2(9);
+        mapper.cpu_write(Addr(0xa000), 3).unwrap();
+        assert_eq!(prg(&mapper), [3, 13, 14, 15]);
+
+        let mut mapper = mmc2(10);
+        mapper.cpu_write(Addr(0xa000), 3).unwrap();
+        assert_eq!(prg(&mapper), [6, 7, 14, 15]);
+    }
+}
@@ -0,0 +1,318 @@
+use std::fmt::Display;
+
+use crate::system::{addr::Addr, bus::open_bus, cart::Header};
+
+use super::{Mapper, Mirroring};
+
+
+// INES 04
+pub struct MMC3 {
+    pub(crate) prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    pub(crate) chr_rom: Vec<u8>,
+    /// Carts without CHR ROM have 8 KB of CHR RAM instead
+    chr_writable: bool,
+    four_screen: bool,
+
+    /// Bank select register ($8000)
+    bank_select: u8,
+    /// R0-R7, set through $8001
+    banks: [u8; 8],
+    horizontal_mirroring: bool,
+    prg_ram_enabled: bool,
+    prg_ram_protected: bool,
+
+    irq_latch: u8,
+    irq_counter: u8,
+    irq_reload: bool,
+    irq_enabled: bool,
+    irq_pending: bool,
+
+    /// Last seen state of PPU A12
+    a12: bool,
+    /// CPU cycles since A12 went low, the counter ignores rises after short dips
+    a12_low_cycles: u8,
+}
+
+impl MMC3 {
+    const PRG_RAM_SIZE: usize = 0x2000;
+    const PRG_BANK_SIZE: usize = 0x2000;
+    const CHR_BANK_SIZE: usize = 0x0400;
+
+    /// CPU cycles A12 has to stay low for a rise to clock the IRQ counter
+    const A12_FILTER_CYCLES: u8 = 3;
+
+    pub fn new(header: &Header, prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Self {
+        Self {
+            prg_rom,
+            prg_ram: vec![0; Self::PRG_RAM_SIZE],
+            chr_rom,
+            chr_writable: header.chr_rom_size == 0,
+            four_screen: header.no_mirror,
+            bank_select: 0,
+            banks: [0, 2, 4, 5, 6, 7, 0, 1],
+            horizontal_mirroring: !header.vertical_mirroring,
+            prg_ram_enabled: true,
+            prg_ram_protected: false,
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_reload: false,
+            irq_enabled: false,
+            irq_pending: false,
+            a12: false,
+            a12_low_cycles: 0,
+        }
+    }
+
+    fn prg_addr(&self, addr: Addr) -> usize {
+        let bank_count = self.prg_rom.len() / Self::PRG_BANK_SIZE;
+        let second_last = bank_count - 2;
+        let swap_prg = self.bank_select & 0b0100_0000 != 0;
+
+        let bank = match ((addr.0 - 0x8000) / 0x2000, swap_prg) {
+            (0, false) | (2, true) => self.banks[6] as usize,
+            (0, true) | (2, false) => second_last,
+            (1, _) => self.banks[7] as usize,
+            _ => bank_count - 1,
+        };
+        (bank % bank_count) * Self::PRG_BANK_SIZE + (addr.0 as usize % Self::PRG_BANK_SIZE)
+    }
+
+    fn chr_addr(&self, addr: Addr) -> usize {
+        // A12 inversion swaps the 2 KB banks at $0000 with the 1 KB banks at $1000
+        let inverted = self.bank_select & 0b1000_0000 != 0;
+        let addr = if inverted {addr.0 ^ 0x1000} else {addr.0} as usize;
+
+        let bank = match addr / Self::CHR_BANK_SIZE {
+            0 => self.banks[0] & 0xfe,
+            1 => self.banks[0] | 0x01,
+            2 => self.banks[1] & 0xfe,
+            3 => self.banks[1] | 0x01,
+            slot => self.banks[slot - 2],
+        } as usize;
+        (bank * Self::CHR_BANK_SIZE + addr % Self::CHR_BANK_SIZE) % self.chr_rom.len()
+    }
+
+    fn clock_irq_counter(&mut self) {
+        if self.irq_counter == 0 || self.irq_reload {
+            self.irq_counter = self.irq_latch;
+            self.irq_reload = false;
+        } else {
+            self.irq_counter -= 1;
+        }
+
+        if self.irq_counter == 0 && self.irq_enabled {
+            self.irq_pending = true;
+        }
+    }
+}
+
+impl Mapper for MMC3 {
+    fn ppu_write(&mut self, addr: Addr, value: u8) -> anyhow::Result<()> {
+        if self.chr_writable {
+            let chr_addr = self.chr_addr(addr);
+            self.chr_rom[chr_addr] = value;
+        }
+        Ok(())
+    }
+
+    fn cpu_write(&mut self, addr: Addr, value: u8) -> anyhow::Result<()> {
+        if addr < 0x6000 {
+            anyhow::bail!("tried to write 0x{value:02x} outside pgm range: {addr}")
+        } else if addr < 0x8000 {
+            if self.prg_ram_enabled && !self.prg_ram_protected {
+                self.prg_ram[addr.0 as usize - 0x6000] = value;
+            }
+            return Ok(())
+        }
+
+        let even = addr.0 & 1 == 0;
+        match (addr.0 & 0xe000, even) {
+            (0x8000, true) => self.bank_select = value,
+            (0x8000, false) => self.banks[(self.bank_select & 0b111) as usize] = value,
+            (0xa000, true) => self.horizontal_mirroring = value & 1 != 0,
+            (0xa000, false) => {
+                self.prg_ram_enabled = value & 0b1000_0000 != 0;
+                self.prg_ram_protected = value & 0b0100_0000 != 0;
+            }
+            (0xc000, true) => self.irq_latch = value,
+            (0xc000, false) => {
+                self.irq_counter = 0;
+                self.irq_reload = true;
+            }
+            (0xe000, true) => {
+                self.irq_enabled = false;
+                self.irq_pending = false;
+            }
+            (0xe000, false) => self.irq_enabled = true,
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    fn ppu_peek(&self, addr: Addr) -> anyhow::Result<u8> {
+        Ok(self.chr_rom[self.chr_addr(addr)])
+    }
+
+    fn cpu_read(&self, addr: Addr) -> anyhow::Result<u8> {
+        if addr < 0x6000 {
+            anyhow::bail!("read outside pgm range: {addr}")
+        } else if addr < 0x8000 {
+            // a disabled chip leaves the bus floating
+            let value = if self.prg_ram_enabled {self.prg_ram[addr.0 as usize - 0x6000]} else {open_bus(addr)};
+            Ok(value)
+        } else {
+            Ok(self.prg_rom[self.prg_addr(addr)])
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn mirroring(&self) -> Option<Mirroring> {
+        Some(if self.four_screen {
+            Mirroring::FourScreen
+        } else if self.horizontal_mirroring {
+            Mirroring::Horizontal
+        } else {
+            Mirroring::Vertical
+        })
+    }
+
+    fn ppu_address(&mut self, addr: Addr) {
+        let a12 = addr.0 & 0x1000 != 0;
+        if a12 && !self.a12 && self.a12_low_cycles >= Self::A12_FILTER_CYCLES {
+            self.clock_irq_counter();
+        }
+        if a12 {
+            self.a12_low_cycles = 0;
+        }
+        self.a12 = a12;
+    }
+
+    fn tick(&mut self) {
+        if !self.a12 {
+            self.a12_low_cycles = self.a12_low_cycles.saturating_add(1);
+        }
+    }
+}
+
+impl Display for MMC3 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("MMC3")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{mappers::Mapper, system::{addr::Addr, cart::Header}};
+
+    use super::MMC3;
+
+    fn mmc3() -> MMC3 {
+        // every 8 KB PRG bank and 1 KB CHR bank is filled with its own number
+        let prg_rom = (0..8).flat_map(|bank| [bank; 0x2000]).collect();
+        let chr_rom = (0..8).flat_map(|bank| [bank; 0x0400]).collect();
+        MMC3::new(&Header::for_tests(4, 0x10000, 0x2000), prg_rom, chr_rom)
+    }
+
+    /// One scanline with the background at $0000 and sprites at $1000: 85
+    /// CPU cycles of background fetches, then 8 sprites whose pattern
+    /// fetches are split by a short nametable fetch, then the next line's
+    /// first tiles
+    fn scanline(mapper: &mut MMC3) {
+        for _ in 0..85 {
+            mapper.ppu_address(Addr(0x0000));
+            mapper.tick();
+        }
+        for _ in 0..8 {
+            mapper.ppu_address(Addr(0x2000));
+            mapper.tick();
+            mapper.ppu_address(Addr(0x1000));
+            mapper.ppu_address(Addr(0x1008));
+            mapper.tick();
+        }
+        for _ in 0..13 {
+            mapper.ppu_address(Addr(0x0000));
+            mapper.tick();
+        }
+    }
+
+    #[test]
+    fn counts_one_line_per_a12_rise() {
+        let mut mapper = mmc3();
+        mapper.cpu_write(Addr(0xc000), 2).unwrap();
+        mapper.cpu_write(Addr(0xc001), 0).unwrap();
+        mapper.cpu_write(Addr(0xe001), 0).unwrap();
+
+        // the first clock reloads the counter, then it counts down to 0
+        for counter in [2, 1] {
+            scanline(&mut mapper);
+            assert_eq!(mapper.irq_counter, counter);
+            assert!(!mapper.irq());
+        }
+        scanline(&mut mapper);
+        assert_eq!(mapper.irq_counter, 0);
+        assert!(mapper.irq());
+
+        // $E000 acknowledges and disables, so reaching 0 again stays quiet
+        mapper.cpu_write(Addr(0xe000), 0).unwrap();
+        assert!(!mapper.irq());
+        (0..3).for_each(|_| scanline(&mut mapper));
+        assert_eq!(mapper.irq_counter, 0);
+        assert!(!mapper.irq());
+
+        // with a latch of 0 every clock reloads 0 and fires
+        mapper.cpu_write(Addr(0xc000), 0).unwrap();
+        mapper.cpu_write(Addr(0xc001), 0).unwrap();
+        mapper.cpu_write(Addr(0xe001), 0).unwrap();
+        scanline(&mut mapper);
+        assert!(mapper.irq());
+    }
+
+    #[test]
+    fn short_a12_dips_are_filtered() {
+        let mut mapper = mmc3();
+        mapper.cpu_write(Addr(0xc000), 5).unwrap();
+        mapper.cpu_write(Addr(0xc001), 0).unwrap();
+
+        mapper.ppu_address(Addr(0x1000));
+        for _ in 0..3 {
+            mapper.ppu_address(Addr(0x0000));
+            mapper.tick();
+            mapper.tick();
+            mapper.ppu_address(Addr(0x1000));
+        }
+        assert_eq!(mapper.irq_counter, 0);
+
+        mapper.ppu_address(Addr(0x0000));
+        (0..3).for_each(|_| mapper.tick());
+        mapper.ppu_address(Addr(0x1000));
+        assert_eq!(mapper.irq_counter, 5);
+    }
+
+    #[test]
+    fn bank_select_swaps_prg_and_chr() {
+        let mut mapper = mmc3();
+        for (register, bank) in [2, 4, 6, 7, 0, 1, 1, 2].into_iter().enumerate() {
+            mapper.cpu_write(Addr(0x8000), register as u8).unwrap();
+            mapper.cpu_write(Addr(0x8001), bank).unwrap();
+        }
+        let prg = |mapper: &MMC3| [0x8000, 0xa000, 0xc000, 0xe000].map(|addr| mapper.cpu_read(Addr(addr)).unwrap());
+        let chr = |mapper: &MMC3| [0, 1, 2, 3, 4, 5, 6, 7].map(|slot| mapper.ppu_peek(Addr(slot * 0x400)).unwrap());
+
+        assert_eq!(prg(&mapper), [1, 2, 6, 7]);
+        assert_eq!(chr(&mapper), [2, 3, 4, 5, 6, 7, 0, 1]);
+
+        // bit 6 swaps $8000 with the fixed second-last bank at $C000
+        mapper.cpu_write(Addr(0x8000), 0b0100_0000).unwrap();
+        assert_eq!(prg(&mapper), [6, 2, 1, 7]);
+        assert_eq!(chr(&mapper), [2, 3, 4, 5, 6, 7, 0, 1]);
+
+        // bit 7 swaps the 2 KB banks with the 1 KB banks
+        mapper.cpu_write(Addr(0x8000), 0b1000_0000).unwrap();
+        assert_eq!(prg(&mapper), [1, 2, 6, 7]);
+        assert_eq!(chr(&mapper), [6, 7, 0, 1, 2, 3, 4, 5]);
+    }
+}
@@ -0,0 +1,603 @@
+use std::fmt::Display;
+
+use crate::system::{addr::Addr, apu::{mixer, pulse::{Pulse, PulseChannel}}, cart::Header};
+
+use super::{Mapper, Mirroring};
+
+
+// INES 05
+pub struct MMC5 {
+    pub(crate) prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    pub(crate) chr_rom: Vec<u8>,
+    /// Carts without CHR ROM have 8 KB of CHR RAM instead
+    chr_writable: bool,
+    exram: Vec<u8>,
+
+    prg_mode: u8,
+    chr_mode: u8,
+    /// $5102/$5103, PRG RAM is only writable with 2 and 1 in them
+    prg_ram_protect: [u8; 2],
+    /// $5104: 0 nametable, 1 extended attributes, 2 RAM, 3 read-only RAM
+    exram_mode: u8,
+    /// $5105, two bits per nametable: VRAM page A or B, ExRAM or fill mode
+    nametables: u8,
+    fill_tile: u8,
+    fill_attr: u8,
+    /// $5113-$5117, bit 7 picks ROM over RAM
+    prg_banks: [u8; 5],
+    /// $5120-$5127 for sprites and $5128-$512B for the background
+    chr_banks: [u16; 12],
+    /// $5130, the upper bits of the next CHR bank written
+    chr_upper: u8,
+    /// Whether $5128-$512B were written after $5120-$5127
+    chr_set_b_written: bool,
+    /// Snooped from PPUCTRL, the background only gets its own CHR banks with 8x16 sprites
+    tall_sprites: bool,
+
+    split_control: u8,
+    split_scroll: u8,
+    split_bank: u8,
+
+    multiplicand: u8,
+    multiplier: u8,
+
+    irq_target: u8,
+    irq_enabled: bool,
+    irq_pending: bool,
+
+    /// Scanline detection watches the PPU bus for the same nametable address
+    /// three reads in a row, which only happens at the start of a line
+    in_frame: bool,
+    scanline: u8,
+    last_addr: Option<u16>,
+    repeats: u8,
+    /// PPU reads since the start of the line
+    fetch: u16,
+    /// CPU cycles without a PPU read, the frame ends after 3
+    idle_cycles: u8,
+    /// The ExRAM byte for the background tile being fetched
+    tile_ext: u8,
+    /// Whether the background tile being fetched is in the split region
+    tile_split: bool,
+    tile_column: u16,
+
+    pulses: [Pulse; 2],
+    pcm: u8,
+    pcm_read_mode: bool,
+    audio_cycles: u64,
+}
+
+impl MMC5 {
+    const PRG_RAM_SIZE: usize = 0x10000;
+    const PRG_BANK_SIZE: usize = 0x2000;
+    const EXRAM_SIZE: usize = 0x400;
+
+    /// PPU reads on a line: 32 background tiles, 8 sprites and 2 prefetched tiles
+    const SPRITE_FETCHES: std::ops::Range<u16> = 128..160;
+    const PREFETCHES: std::ops::Range<u16> = 160..168;
+
+    /// The length counters and envelopes are clocked at a fixed 240 Hz
+    const AUDIO_FRAME_CYCLES: u64 = 7457;
+
+    pub fn new(header: &Header, prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Self {
+        Self {
+            prg_rom,
+            prg_ram: vec![0; Self::PRG_RAM_SIZE],
+            chr_rom,
+            chr_writable: header.chr_rom_size == 0,
+            exram: vec![0; Self::EXRAM_SIZE],
+            prg_mode: 3,
+            chr_mode: 0,
+            prg_ram_protect: [0; 2],
+            exram_mode: 0,
+            nametables: 0,
+            fill_tile: 0,
+            fill_attr: 0,
+            prg_banks: [0, 0, 0, 0, 0xff],
+            chr_banks: [0; 12],
+            chr_upper: 0,
+            chr_set_b_written: false,
+            tall_sprites: false,
+            split_control: 0,
+            split_scroll: 0,
+            split_bank: 0,
+            multiplicand: 0xff,
+            multiplier: 0xff,
+            irq_target: 0,
+            irq_enabled: false,
+            irq_pending: false,
+            in_frame: false,
+            scanline: 0,
+            last_addr: None,
+            repeats: 0,
+            fetch: 0,
+            idle_cycles: 0,
+            tile_ext: 0,
+            tile_split: false,
+            tile_column: 0,
+            pulses: [Pulse::new(PulseChannel::Expansion), Pulse::new(PulseChannel::Expansion)],
+            pcm: 0,
+            pcm_read_mode: false,
+            audio_cycles: 0,
+        }
+    }
+
+    /// Where a CPU address in $8000-$FFFF lands, as ROM or RAM and an offset
+    fn prg_addr(&self, addr: Addr) -> (bool, usize) {
+        let slot = (addr.0 as usize - 0x8000) / Self::PRG_BANK_SIZE;
+
+        // the register for the slot, and how many 8 KB banks it switches at once
+        let (reg, span) = match (self.prg_mode, slot) {
+            (0, _) => (4, 4),
+            (1, 0 | 1) | (2, 0 | 1) => (2, 2),
+            (1, _) => (4, 2),
+            (_, slot) => (slot + 1, 1),
+        };
+        let value = self.prg_banks[reg] as usize;
+        let bank = (value & 0x7f & !(span - 1)) + slot % span;
+        // $5117 always maps ROM
+        let rom = reg == 4 || value & 0x80 != 0;
+        (rom, bank * Self::PRG_BANK_SIZE + addr.0 as usize % Self::PRG_BANK_SIZE)
+    }
+
+    fn prg_ram_addr(&self, offset: usize) -> usize {
+        offset % Self::PRG_RAM_SIZE
+    }
+
+    fn prg_ram_writable(&self) -> bool {
+        self.prg_ram_protect == [0b10, 0b01]
+    }
+
+    /// Whether the background uses $5128-$512B for the current fetch
+    fn uses_set_b(&self) -> bool {
+        if self.tall_sprites && self.in_frame {
+            !Self::SPRITE_FETCHES.contains(&self.fetch)
+        } else {
+            // otherwise whichever set was written last applies to everything
+            self.chr_set_b_written
+        }
+    }
+
+    fn background_fetch(&self) -> bool {
+        self.in_frame && !Self::SPRITE_FETCHES.contains(&self.fetch)
+    }
+
+    fn chr_addr(&self, addr: Addr) -> usize {
+        let addr = addr.0 as usize & 0x1fff;
+        let banks = &self.chr_banks;
+
+        let (bank, size) = if self.uses_set_b() {
+            // the background set covers $0000-$0FFF and repeats at $1000
+            match self.chr_mode {
+                0 => (banks[11], 0x2000),
+                1 => (banks[11], 0x1000),
+                2 => (banks[9 + 2 * ((addr & 0x0fff) / 0x800)], 0x800),
+                _ => (banks[8 + (addr & 0x0fff) / 0x400], 0x400),
+            }
+        } else {
+            match self.chr_mode {
+                0 => (banks[7], 0x2000),
+                1 => (banks[3 + 4 * (addr / 0x1000)], 0x1000),
+                2 => (banks[1 + 2 * (addr / 0x800)], 0x800),
+                _ => (banks[addr / 0x400], 0x400),
+            }
+        };
+        (bank as usize * size + addr % size) % self.chr_rom.len()
+    }
+
+    /// The split region covers the tiles left or right of the split column
+    fn in_split(&self, column: u16) -> bool {
+        let threshold = (self.split_control & 0x1f) as u16;
+        let right_side = self.split_control & 0b0100_0000 != 0;
+        self.split_control & 0b1000_0000 != 0
+            && self.exram_mode < 2
+            && if right_side {column >= threshold} else {column < threshold}
+    }
+
+    /// The split region scrolls vertically on its own, prefetched tiles are for the next line
+    fn split_y(&self) -> u16 {
+        let line = self.scanline as u16 + if Self::PREFETCHES.contains(&self.fetch) {1} else {0};
+        (self.split_scroll as u16 + line) % 240
+    }
+
+    fn start_scanline(&mut self) {
+        if self.in_frame {
+            self.scanline = self.scanline.wrapping_add(1);
+            if self.scanline == self.irq_target {
+                self.irq_pending = true;
+            }
+        } else {
+            self.in_frame = true;
+            self.scanline = 0;
+            self.irq_pending = false;
+        }
+    }
+
+    fn end_frame(&mut self) {
+        self.in_frame = false;
+        self.last_addr = None;
+        self.repeats = 0;
+    }
+
+    /// Tracks the PPU reads for scanline detection. Only reads count, the
+    /// chip never sees the addresses set through $2006 or PPUDATA writes.
+    fn observe_read(&mut self, addr: Addr) {
+        self.idle_cycles = 0;
+
+        let addr = addr.0 & 0x3fff;
+        self.repeats = if self.last_addr == Some(addr) {self.repeats + 1} else {0};
+        self.last_addr = Some(addr);
+        self.fetch = self.fetch.saturating_add(1);
+
+        let nametable = (0x2000..0x3000).contains(&addr);
+        if nametable && self.repeats == 2 {
+            self.start_scanline();
+            self.fetch = 0;
+        }
+
+        // latch what the upcoming attribute and pattern fetches of the tile need
+        if nametable && self.background_fetch() && self.fetch % 4 == 0 {
+            self.tile_column = if Self::PREFETCHES.contains(&self.fetch) {
+                (self.fetch - Self::PREFETCHES.start) / 4
+            } else {
+                self.fetch / 4 + 2
+            };
+            self.tile_split = self.in_split(self.tile_column);
+            self.tile_ext = self.exram[addr as usize & 0x3ff];
+        }
+    }
+
+    fn set_chr_bank(&mut self, index: usize, value: u8) {
+        self.chr_banks[index] = ((self.chr_upper as u16 & 0b11) << 8) | value as u16;
+        self.chr_set_b_written = index >= 8;
+    }
+}
+
+impl Mapper for MMC5 {
+    fn ppu_write(&mut self, addr: Addr, value: u8) -> anyhow::Result<()> {
+        if self.chr_writable {
+            let chr_addr = self.chr_addr(addr);
+            self.chr_rom[chr_addr] = value;
+        }
+        Ok(())
+    }
+
+    fn cpu_write(&mut self, addr: Addr, value: u8) -> anyhow::Result<()> {
+        if addr < 0x6000 {
+            anyhow::bail!("tried to write 0x{value:02x} outside pgm range: {addr}")
+        }
+        if !self.prg_ram_writable() {
+            return Ok(())
+        }
+
+        if addr < 0x8000 {
+            let offset = (self.prg_banks[0] as usize & 0x07) * Self::PRG_BANK_SIZE + (addr.0 as usize - 0x6000);
+            let ram_addr = self.prg_ram_addr(offset);
+            self.prg_ram[ram_addr] = value;
+        } else if let (false, offset) = self.prg_addr(addr) {
+            let ram_addr = self.prg_ram_addr(offset);
+            self.prg_ram[ram_addr] = value;
+        }
+        Ok(())
+    }
+
+    fn ppu_peek(&self, addr: Addr) -> anyhow::Result<u8> {
+        Ok(self.chr_rom[self.chr_addr(addr)])
+    }
+
+    fn cpu_read(&self, addr: Addr) -> anyhow::Result<u8> {
+        if addr < 0x6000 {
+            anyhow::bail!("read outside pgm range: {addr}")
+        } else if addr < 0x8000 {
+            let offset = (self.prg_banks[0] as usize & 0x07) * Self::PRG_BANK_SIZE + (addr.0 as usize - 0x6000);
+            Ok(self.prg_ram[self.prg_ram_addr(offset)])
+        } else {
+            match self.prg_addr(addr) {
+                (true, offset) => Ok(self.prg_rom[offset % self.prg_rom.len()]),
+                (false, offset) => Ok(self.prg_ram[self.prg_ram_addr(offset)]),
+            }
+        }
+    }
+
+    fn ppu_read(&mut self, addr: Addr) -> anyhow::Result<u8> {
+        self.observe_read(addr);
+        if !self.background_fetch() {
+            return self.ppu_peek(addr)
+        }
+
+        let offset = if self.tile_split {
+            // the split region has its own 4 KB bank and fine Y
+            let fine_y = self.split_y() as usize % 8;
+            self.split_bank as usize * 0x1000 + (addr.0 as usize & 0x0ff8) + fine_y
+        } else if self.exram_mode == 1 {
+            // extended attributes pick a 4 KB bank for each tile
+            let bank = ((self.chr_upper as usize & 0b11) << 6) | (self.tile_ext as usize & 0x3f);
+            bank * 0x1000 + (addr.0 as usize & 0x0fff)
+        } else {
+            return self.ppu_peek(addr)
+        };
+        Ok(self.chr_rom[offset % self.chr_rom.len()])
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending && self.irq_enabled
+    }
+
+    fn mirroring(&self) -> Option<Mirroring> {
+        // only VRAM pages A and B end up here, ExRAM and fill mode are answered by `nametable_read`
+        let page = |n: u8| (self.nametables >> (n * 2)) & 1;
+        Some(Mirroring::Custom([page(0), page(1), page(2), page(3)]))
+    }
+
+    fn nametable_read(&mut self, addr: Addr) -> Option<u8> {
+        self.observe_read(addr);
+        let offset = addr.0 as usize & 0x3ff;
+        let attribute = offset >= 0x3c0;
+
+        if self.background_fetch() && self.tile_split {
+            let y = self.split_y() as usize;
+            let column = self.tile_column as usize % 32;
+            return Some(if attribute {
+                let attributes = self.exram[0x3c0 + (y / 32) * 8 + column / 4];
+                let shift = ((y / 16) & 1) * 4 + ((column / 2) & 1) * 2;
+                ((attributes >> shift) & 0b11) * 0x55
+            } else {
+                self.exram[(y / 8) * 32 + column]
+            })
+        }
+        if self.background_fetch() && self.exram_mode == 1 && attribute {
+            // the palette from ExRAM, repeated for every quadrant
+            return Some((self.tile_ext >> 6) * 0x55)
+        }
+
+        let table = (addr.0 as usize & 0x0fff) / 0x400;
+        match (self.nametables >> (table * 2)) & 0b11 {
+            0b10 => Some(if self.exram_mode < 2 {self.exram[offset]} else {0}),
+            0b11 => Some(if attribute {(self.fill_attr & 0b11) * 0x55} else {self.fill_tile}),
+            _ => None,
+        }
+    }
+
+    fn nametable_write(&mut self, addr: Addr, value: u8) -> bool {
+        let table = (addr.0 as usize & 0x0fff) / 0x400;
+        match (self.nametables >> (table * 2)) & 0b11 {
+            0b10 => {
+                if self.exram_mode < 2 {
+                    self.exram[addr.0 as usize & 0x3ff] = value;
+                }
+                true
+            }
+            0b11 => true,
+            _ => false,
+        }
+    }
+
+    fn expansion_peek(&self, addr: Addr) -> Option<u8> {
+        match addr.0 {
+            0x5010 => Some(if self.pcm_read_mode {0x01} else {0x00}),
+            0x5015 => Some(
+                if self.pulses[0].length.active() {1 << 0} else {0}
+                | if self.pulses[1].length.active() {1 << 1} else {0}
+            ),
+            0x5204 => Some(if self.irq_pending {1 << 7} else {0} | if self.in_frame {1 << 6} else {0}),
+            0x5205 => Some((self.multiplicand as u16 * self.multiplier as u16) as u8),
+            0x5206 => Some(((self.multiplicand as u16 * self.multiplier as u16) >> 8) as u8),
+            0x5c00..=0x5fff if self.exram_mode >= 2 => Some(self.exram[addr.0 as usize - 0x5c00]),
+            _ => None,
+        }
+    }
+
+    fn expansion_read(&mut self, addr: Addr) -> Option<u8> {
+        let value = self.expansion_peek(addr);
+        // reading the status acknowledges the IRQ
+        if addr == 0x5204 {
+            self.irq_pending = false;
+        }
+        value
+    }
+
+    fn expansion_write(&mut self, addr: Addr, value: u8) {
+        match addr.0 {
+            // the pulses have no sweep unit
+            0x5000 | 0x5002 | 0x5003 => self.pulses[0].write((addr.0 - 0x5000) as u8, value),
+            0x5004 | 0x5006 | 0x5007 => self.pulses[1].write((addr.0 - 0x5004) as u8, value),
+            // PCM read mode would take samples from CPU reads of $8000-$BFFF, only write mode plays
+            0x5010 => self.pcm_read_mode = value & 1 != 0,
+            0x5011 if !self.pcm_read_mode && value != 0 => self.pcm = value,
+            0x5015 => {
+                self.pulses[0].length.set_enabled(value & (1 << 0) != 0);
+                self.pulses[1].length.set_enabled(value & (1 << 1) != 0);
+            }
+            0x5100 => self.prg_mode = value & 0b11,
+            0x5101 => self.chr_mode = value & 0b11,
+            0x5102 => self.prg_ram_protect[0] = value & 0b11,
+            0x5103 => self.prg_ram_protect[1] = value & 0b11,
+            0x5104 => self.exram_mode = value & 0b11,
+            0x5105 => self.nametables = value,
+            0x5106 => self.fill_tile = value,
+            0x5107 => self.fill_attr = value & 0b11,
+            0x5113..=0x5117 => self.prg_banks[addr.0 as usize - 0x5113] = value,
+            0x5120..=0x512b => self.set_chr_bank(addr.0 as usize - 0x5120, value),
+            0x5130 => self.chr_upper = value & 0b11,
+            0x5200 => self.split_control = value,
+            0x5201 => self.split_scroll = value,
+            0x5202 => self.split_bank = value,
+            0x5203 => self.irq_target = value,
+            0x5204 => self.irq_enabled = value & 0b1000_0000 != 0,
+            0x5205 => self.multiplicand = value,
+            0x5206 => self.multiplier = value,
+            0x5c00..=0x5fff if self.exram_mode != 3 => self.exram[addr.0 as usize - 0x5c00] = value,
+            _ => {}
+        }
+    }
+
+    fn cpu_bus_write(&mut self, addr: Addr, value: u8) {
+        // PPUCTRL and its mirrors
+        if (0x2000..0x4000).contains(&addr.0) && addr.0 % 8 == 0 {
+            self.tall_sprites = value & 0b0010_0000 != 0;
+        }
+    }
+
+    fn tick(&mut self) {
+        self.idle_cycles = self.idle_cycles.saturating_add(1);
+        if self.idle_cycles >= 3 && self.in_frame {
+            self.end_frame();
+        }
+
+        self.audio_cycles += 1;
+        if self.audio_cycles % 2 == 0 {
+            self.pulses.iter_mut().for_each(Pulse::clock_timer);
+        }
+        if self.audio_cycles % Self::AUDIO_FRAME_CYCLES == 0 {
+            for pulse in &mut self.pulses {
+                pulse.clock_quarter_frame();
+                pulse.clock_half_frame();
+            }
+        }
+    }
+
+    fn audio_output(&self) -> f32 {
+        // the pulses match the APU's, the PCM channel is about as loud as the DMC at twice the resolution
+        mixer::mix(self.pulses[0].output(), self.pulses[1].output(), 0, 0, self.pcm >> 1)
+    }
+}
+
+impl Display for MMC5 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("MMC5")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{mappers::Mapper, system::{addr::Addr, cart::Header}};
+
+    use super::MMC5;
+
+    /// 128 KB of PRG ROM and 64 KB of CHR ROM, every bank filled with its number
+    fn mmc5() -> MMC5 {
+        let prg_rom = (0..16).flat_map(|bank| vec![bank as u8; 0x2000]).collect::<Vec<_>>();
+        let chr_rom = (0..64).flat_map(|bank| vec![bank as u8; 0x400]).collect::<Vec<_>>();
+        MMC5::new(&Header::for_tests(5, prg_rom.len(), chr_rom.len()), prg_rom, chr_rom)
+    }
+
+    fn prg_banks(mapper: &MMC5) -> [u8; 4] {
+        [0x8000, 0xa000, 0xc000, 0xe000].map(|addr| mapper.cpu_read(Addr(addr)).unwrap())
+    }
+
+    #[test]
+    fn multiplier() {
+        let mut mapper = mmc5();
+        mapper.expansion_write(Addr(0x5205), 200);
+        mapper.expansion_write(Addr(0x5206), 100);
+        assert_eq!(mapper.expansion_read(Addr(0x5205)), Some((20000 & 0xff) as u8));
+        assert_eq!(mapper.expansion_read(Addr(0x5206)), Some((20000 >> 8) as u8));
+    }
+
+    #[test]
+    fn prg_modes() {
+        let mut mapper = mmc5();
+
+        // one 32 KB bank, the low two bits are ignored
+        mapper.expansion_write(Addr(0x5100), 0);
+        mapper.expansion_write(Addr(0x5117), 0x87);
+        assert_eq!(prg_banks(&mapper), [4, 5, 6, 7]);
+
+        // two 16 KB banks
+        mapper.expansion_write(Addr(0x5100), 1);
+        mapper.expansion_write(Addr(0x5115), 0x83);
+        mapper.expansion_write(Addr(0x5117), 0x8d);
+        assert_eq!(prg_banks(&mapper), [2, 3, 12, 13]);
+
+        // 16 KB and two 8 KB banks
+        mapper.expansion_write(Addr(0x5100), 2);
+        mapper.expansion_write(Addr(0x5115), 0x85);
+        mapper.expansion_write(Addr(0x5116), 0x89);
+        mapper.expansion_write(Addr(0x5117), 0x8f);
+        assert_eq!(prg_banks(&mapper), [4, 5, 9, 15]);
+
+        // four 8 KB banks
+        mapper.expansion_write(Addr(0x5100), 3);
+        for (i, bank) in [0x81, 0x82, 0x83, 0x84].into_iter().enumerate() {
+            mapper.expansion_write(Addr(0x5114 + i as u16), bank);
+        }
+        assert_eq!(prg_banks(&mapper), [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn prg_ram_in_the_rom_area() {
+        let mut mapper = mmc5();
+        mapper.expansion_write(Addr(0x5114), 0x01);
+        assert_eq!(mapper.prg_addr(Addr(0x8001)), (false, 0x2001));
+
+        // writes only land once both protect registers are unlocked
+        mapper.cpu_write(Addr(0x8001), 0x42).unwrap();
+        assert_eq!(mapper.cpu_read(Addr(0x8001)).unwrap(), 0x00);
+        mapper.expansion_write(Addr(0x5102), 0b10);
+        mapper.expansion_write(Addr(0x5103), 0b01);
+        mapper.cpu_write(Addr(0x8001), 0x42).unwrap();
+        assert_eq!(mapper.cpu_read(Addr(0x8001)).unwrap(), 0x42);
+
+        // $5117 always maps ROM
+        mapper.expansion_write(Addr(0x5117), 0x01);
+        assert_eq!(mapper.prg_addr(Addr(0xe000)), (true, 0x2000));
+    }
+
+    #[test]
+    fn chr_sets() {
+        let mut mapper = mmc5();
+        mapper.expansion_write(Addr(0x5101), 3);
+        for i in 0..8 {
+            mapper.expansion_write(Addr(0x5120 + i), 10 + i as u8);
+        }
+        for i in 0..4 {
+            mapper.expansion_write(Addr(0x5128 + i), 20 + i as u8);
+        }
+
+        // with 8x8 sprites the set written last covers everything, set B repeats at $1000
+        assert_eq!(mapper.ppu_peek(Addr(0x0000)).unwrap(), 20);
+        assert_eq!(mapper.ppu_peek(Addr(0x1400)).unwrap(), 21);
+        mapper.expansion_write(Addr(0x5123), 13);
+        assert_eq!(mapper.ppu_peek(Addr(0x0c00)).unwrap(), 13);
+        assert_eq!(mapper.ppu_peek(Addr(0x1000)).unwrap(), 14);
+
+        // with 8x16 sprites the background uses set B while rendering
+        mapper.cpu_bus_write(Addr(0x2000), 0b0010_0000);
+        mapper.in_frame = true;
+        mapper.fetch = 2;
+        assert_eq!(mapper.ppu_peek(Addr(0x1400)).unwrap(), 21);
+        mapper.fetch = 130;
+        assert_eq!(mapper.ppu_peek(Addr(0x1400)).unwrap(), 15);
+    }
+
+    #[test]
+    fn status_read_acknowledges_the_irq() {
+        let mut mapper = mmc5();
+        mapper.expansion_write(Addr(0x5203), 2);
+        mapper.expansion_write(Addr(0x5204), 0x80);
+
+        // three reads of the same nametable address start a line
+        for line in 0..3 {
+            for _ in 0..3 {
+                mapper.nametable_read(Addr(0x2000 + line));
+            }
+        }
+        assert!(mapper.irq());
+
+        assert_eq!(mapper.expansion_peek(Addr(0x5204)), Some(0xc0));
+        assert!(mapper.irq());
+        assert_eq!(mapper.expansion_read(Addr(0x5204)), Some(0xc0));
+        assert!(!mapper.irq());
+        assert_eq!(mapper.expansion_read(Addr(0x5204)), Some(0x40));
+    }
+
+    #[test]
+    fn address_changes_alone_dont_start_a_line() {
+        let mut mapper = mmc5();
+        for _ in 0..3 {
+            mapper.ppu_address(Addr(0x2000));
+        }
+        assert_eq!(mapper.expansion_read(Addr(0x5204)), Some(0x00));
+    }
+}
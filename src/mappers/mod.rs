@@ -3,27 +3,204 @@ use std::{fmt::Display};
 use crate::system::{addr::Addr, cart::Header};
 
 
+pub mod axrom;
+pub mod bnrom;
+pub mod cnrom;
+pub mod color_dreams;
+pub mod gxrom;
 pub mod mmc1;
+pub mod mmc2;
+pub mod mmc3;
+pub mod mmc5;
+pub mod nina001;
 pub mod nrom;
 pub mod uxrom;
+pub mod vrc4;
+pub mod vrc6;
+pub mod vrc7;
+mod vrc_irq;
 
+pub use axrom::AxROM;
+pub use bnrom::BNROM;
+pub use cnrom::CNROM;
+pub use color_dreams::ColorDreams;
+pub use gxrom::GxROM;
 pub use mmc1::MMC1;
+pub use mmc2::MMC2;
+pub use mmc3::MMC3;
+pub use mmc5::MMC5;
+pub use nina001::NINA001;
 pub use nrom::NROM;
 pub use uxrom::UxROM;
+pub use vrc4::VRC4;
+pub use vrc6::VRC6;
+pub use vrc7::VRC7;
+
+/// How the 4 nametables map onto the 2 KB of VRAM in the console
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mirroring {
+    Horizontal,
+    Vertical,
+    OneScreenLower,
+    OneScreenUpper,
+    /// The cart provides the extra VRAM for 4 separate nametables
+    FourScreen,
+    /// Each nametable picks its own 1 KB page of VRAM
+    Custom([u8; 4]),
+}
+
+impl Mirroring {
+    /// The fixed mirroring the cart is wired for
+    pub fn from_header(header: &Header) -> Self {
+        if header.no_mirror {
+            Mirroring::FourScreen
+        } else if header.vertical_mirroring {
+            Mirroring::Vertical
+        } else {
+            Mirroring::Horizontal
+        }
+    }
+}
 
 pub trait Mapper where Self: Display {
     fn ppu_write(&mut self, addr: Addr, value: u8) -> anyhow::Result<()>;
     fn cpu_write(&mut self, addr: Addr, value: u8) -> anyhow::Result<()>;
 
-    fn ppu_read(&self, addr: Addr) -> anyhow::Result<u8>;
+    /// Reads CHR memory without the side effects of a PPU fetch
+    fn ppu_peek(&self, addr: Addr) -> anyhow::Result<u8>;
     fn cpu_read(&self, addr: Addr) -> anyhow::Result<u8>;
+
+    /// A CHR fetch by the PPU, which some mappers snoop on to switch banks
+    fn ppu_read(&mut self, addr: Addr) -> anyhow::Result<u8> {
+        self.ppu_peek(addr)
+    }
+
+    /// Whether the mapper is asserting the CPU IRQ line
+    fn irq(&self) -> bool {
+        false
+    }
+
+    /// The nametable mirroring, `None` if it's fixed by the header
+    fn mirroring(&self) -> Option<Mirroring> {
+        None
+    }
+
+    /// Called with every address the PPU puts on its bus, including the
+    /// nametable fetches that don't reach the cart's CHR memory
+    fn ppu_address(&mut self, _addr: Addr) {}
+
+    /// Called once per CPU cycle (M2)
+    fn tick(&mut self) {}
+
+    /// A nametable fetch the cart answers itself instead of the console's
+    /// VRAM, `None` if it's left to the mirroring
+    fn nametable_read(&mut self, _addr: Addr) -> Option<u8> {
+        None
+    }
+
+    /// A nametable write through PPUDATA, returns whether the cart took it
+    fn nametable_write(&mut self, _addr: Addr, _value: u8) -> bool {
+        false
+    }
+
+    /// Reads the expansion area at $4020-$5FFF without side effects, `None`
+    /// leaves the bus floating
+    fn expansion_peek(&self, _addr: Addr) -> Option<u8> {
+        None
+    }
+
+    /// A CPU read from the expansion area, which can acknowledge IRQs and the like
+    fn expansion_read(&mut self, addr: Addr) -> Option<u8> {
+        self.expansion_peek(addr)
+    }
+
+    /// Writes to the expansion area at $4020-$5FFF
+    fn expansion_write(&mut self, _addr: Addr, _value: u8) {}
+
+    /// Called with every CPU write, for mappers that snoop on registers
+    /// outside the cart
+    fn cpu_bus_write(&mut self, _addr: Addr, _value: u8) {}
+
+    /// The level of the cart's expansion audio, mixed on top of the APU
+    fn audio_output(&self) -> f32 {
+        0.0
+    }
 }
 
 pub fn new(header: &Header, prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> anyhow::Result<Box<dyn Mapper>> {
      match header.mapper_id {
-        0 => Ok(Box::new(NROM::new(&header, prg_rom, chr_rom))),
-        1 => Ok(Box::new(MMC1::new(&header, prg_rom, chr_rom))),
-        2 => Ok(Box::new(UxROM::new(&header, prg_rom, chr_rom))),
+        0 => Ok(Box::new(NROM::new(header, prg_rom, chr_rom))),
+        1 => Ok(Box::new(MMC1::new(header, prg_rom, chr_rom))),
+        2 => Ok(Box::new(UxROM::new(header, prg_rom, chr_rom))),
+        3 => Ok(Box::new(CNROM::new(header, prg_rom, chr_rom))),
+        4 => Ok(Box::new(MMC3::new(header, prg_rom, chr_rom))),
+        5 => Ok(Box::new(MMC5::new(header, prg_rom, chr_rom))),
+        7 => Ok(Box::new(AxROM::new(header, prg_rom, chr_rom))),
+        9 | 10 => Ok(Box::new(MMC2::new(header, prg_rom, chr_rom))),
+        11 => Ok(Box::new(ColorDreams::new(header, prg_rom, chr_rom))),
+        21 | 22 | 23 | 25 => Ok(Box::new(VRC4::new(header, prg_rom, chr_rom))),
+        24 | 26 => Ok(Box::new(VRC6::new(header, prg_rom, chr_rom))),
+        // the two boards share a number, NINA-001 is the one with more than 8 KB of CHR ROM
+        34 if header.chr_rom_size > 0x2000 => Ok(Box::new(NINA001::new(header, prg_rom, chr_rom))),
+        34 => Ok(Box::new(BNROM::new(header, prg_rom, chr_rom))),
+        66 => Ok(Box::new(GxROM::new(header, prg_rom, chr_rom))),
+        85 => Ok(Box::new(VRC7::new(header, prg_rom, chr_rom))),
         mid => anyhow::bail!("non-supported mapper id {mid:02x}"),
     }
-}
\ No newline at end of file
+}
+
+/// The value a discrete logic board latches on a write. Without bus conflict
+/// prevention the PRG ROM drives the data bus at the same time as the CPU,
+/// and the 0 bits win.
+fn bus_conflict(mapper: &dyn Mapper, addr: Addr, value: u8, enabled: bool) -> anyhow::Result<u8> {
+    if enabled {
+        Ok(value & mapper.cpu_read(addr)?)
+    } else {
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::system::{addr::Addr, cart::Header};
+
+    use super::{BNROM, Mapper};
+
+    #[test]
+    fn bus_conflicts_and_the_value_with_rom() {
+        let mut prg_rom = vec![0; 0x10000];
+        prg_rom[0x0000] = 0x11;
+        prg_rom[0x8000] = 0x22;
+
+        let mut header = Header::for_tests(34, prg_rom.len(), 0);
+        let mapper = BNROM::new(&header, prg_rom.clone(), vec![]);
+        assert_eq!(super::bus_conflict(&mapper, Addr(0x8000), 0xff, true).unwrap(), 0x11);
+        assert_eq!(super::bus_conflict(&mapper, Addr(0x8000), 0xff, false).unwrap(), 0xff);
+
+        // selecting bank 1 over a ROM byte of 0 latches bank 0 instead
+        header.bus_conflicts = true;
+        let mut mapper = BNROM::new(&header, prg_rom.clone(), vec![]);
+        mapper.cpu_write(Addr(0x8001), 1).unwrap();
+        assert_eq!(mapper.cpu_read(Addr(0x8000)).unwrap(), 0x11);
+
+        header.bus_conflicts = false;
+        let mut mapper = BNROM::new(&header, prg_rom, vec![]);
+        mapper.cpu_write(Addr(0x8001), 1).unwrap();
+        assert_eq!(mapper.cpu_read(Addr(0x8000)).unwrap(), 0x22);
+
+        // nothing answers at $6000-$7FFF, so the bus keeps the high address byte
+        assert_eq!(mapper.cpu_read(Addr(0x6123)).unwrap(), 0x61);
+        assert_eq!(mapper.cpu_read(Addr(0x7fff)).unwrap(), 0x7f);
+    }
+
+    #[test]
+    fn mapper_34_picks_the_board_by_chr_size() {
+        let board = |chr_rom_size: usize| {
+            let header = Header::for_tests(34, 0x8000, chr_rom_size);
+            super::new(&header, vec![0; 0x8000], vec![0; chr_rom_size]).unwrap().to_string()
+        };
+        assert_eq!(board(0), "BNROM");
+        assert_eq!(board(0x2000), "BNROM");
+        assert_eq!(board(0x4000), "NINA-001");
+    }
+}
@@ -0,0 +1,72 @@
+use std::fmt::Display;
+
+use crate::system::{addr::Addr, cart::Header};
+
+use super::Mapper;
+
+
+// INES 34 with CHR ROM
+pub struct NINA001 {
+    pub(crate) prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    pub(crate) chr_rom: Vec<u8>,
+    prg_bank: u8,
+    /// 4 KB banks at $0000 and $1000
+    chr_banks: [u8; 2],
+}
+impl NINA001 {
+    pub fn new(_header: &Header, prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Self {
+        Self {
+            prg_rom,
+            prg_ram: vec![0; 0x2000],
+            chr_rom,
+            prg_bank: 0,
+            chr_banks: [0, 1],
+        }
+    }
+}
+
+impl Mapper for NINA001 {
+    fn ppu_write(&mut self, _addr: Addr, _value: u8) -> anyhow::Result<()> {
+        // CHR ROM
+        Ok(())
+    }
+
+    fn cpu_write(&mut self, addr: Addr, value: u8) -> anyhow::Result<()> {
+        if !(0x6000..0x8000).contains(&addr.0) {
+            return Ok(())
+        }
+        // the registers sit on top of the PRG RAM, which gets written too
+        self.prg_ram[addr.0 as usize - 0x6000] = value;
+        match addr.0 {
+            0x7ffd => self.prg_bank = value & 1,
+            0x7ffe => self.chr_banks[0] = value & 0x0f,
+            0x7fff => self.chr_banks[1] = value & 0x0f,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn ppu_peek(&self, addr: Addr) -> anyhow::Result<u8> {
+        let bank = self.chr_banks[addr.0 as usize / 0x1000] as usize;
+        let chr_addr = bank * 0x1000 + (addr.0 as usize % 0x1000);
+        Ok(self.chr_rom[chr_addr % self.chr_rom.len()])
+    }
+
+    fn cpu_read(&self, addr: Addr) -> anyhow::Result<u8> {
+        if addr < 0x6000 {
+            anyhow::bail!("read outside pgm range: {addr}")
+        } else if addr < 0x8000 {
+            Ok(self.prg_ram[addr.0 as usize - 0x6000])
+        } else {
+            let rom_addr = self.prg_bank as usize * 0x8000 + (addr.0 as usize - 0x8000);
+            Ok(self.prg_rom[rom_addr % self.prg_rom.len()])
+        }
+    }
+}
+
+impl Display for NINA001 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("NINA-001")
+    }
+}
@@ -48,7 +48,7 @@ impl Mapper for NROM {
         anyhow::bail!("tried to write to 0x{value:02x} to cart ({addr}) which is not implemented for mapper {self}");
     }
 
-    fn ppu_read(&self, addr: Addr) -> anyhow::Result<u8> {
+    fn ppu_peek(&self, addr: Addr) -> anyhow::Result<u8> {
         // if self.chr_rom.len() == 0 {
         //     Ok(self.chr_ram[addr.0 as usize])
         // } else {
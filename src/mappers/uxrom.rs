@@ -60,7 +60,7 @@ impl Mapper for UxROM {
         Ok(())
     }
 
-    fn ppu_read(&self, addr: Addr) -> anyhow::Result<u8> {
+    fn ppu_peek(&self, addr: Addr) -> anyhow::Result<u8> {
         Ok(self.chr_ram[addr.0 as usize])
     }
 
@@ -0,0 +1,200 @@
+use std::fmt::Display;
+
+use crate::system::{addr::Addr, cart::Header};
+
+use super::{Mapper, Mirroring, vrc_irq::VrcIrq};
+
+
+// INES 21, 22, 23 and 25
+// The VRC2 boards use a subset of the VRC4 registers, so both run here
+pub struct VRC4 {
+    pub(crate) prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    pub(crate) chr_rom: Vec<u8>,
+    /// Carts without CHR ROM have 8 KB of CHR RAM instead
+    chr_writable: bool,
+    /// The CPU address lines wired to the chip's register select pins, the
+    /// two variants sharing a mapper number never use the other's lines
+    register_lines: [[u16; 2]; 2],
+    /// VRC2a ignores the lowest bit of its CHR banks
+    chr_shift: u8,
+
+    prg_banks: [u8; 2],
+    /// Swaps the $8000 bank with the fixed second-last one at $C000
+    prg_swap: bool,
+    chr_banks: [u16; 8],
+    mirroring: Mirroring,
+    irq: VrcIrq,
+}
+
+impl VRC4 {
+    const PRG_BANK_SIZE: usize = 0x2000;
+    const CHR_BANK_SIZE: usize = 0x0400;
+
+    pub fn new(header: &Header, prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Self {
+        let register_lines = match header.mapper_id {
+            // VRC4a and VRC4c
+            21 => [[1 << 1, 1 << 6], [1 << 2, 1 << 7]],
+            // VRC2a
+            22 => [[1 << 1, 0], [1 << 0, 0]],
+            // VRC2b, VRC4e and VRC4f
+            23 => [[1 << 0, 1 << 2], [1 << 1, 1 << 3]],
+            // VRC2c, VRC4b and VRC4d
+            _ => [[1 << 1, 1 << 3], [1 << 0, 1 << 2]],
+        };
+
+        Self {
+            prg_rom,
+            prg_ram: vec![0; 0x2000],
+            chr_rom,
+            chr_writable: header.chr_rom_size == 0,
+            register_lines,
+            chr_shift: if header.mapper_id == 22 {1} else {0},
+            prg_banks: [0; 2],
+            prg_swap: false,
+            chr_banks: [0; 8],
+            mirroring: Mirroring::from_header(header),
+            irq: VrcIrq::default(),
+        }
+    }
+
+    /// Folds the board's address lines into register 0-3 within the $1000 range
+    fn register(&self, addr: Addr) -> u8 {
+        let line = |lines: [u16; 2]| addr.0 & (lines[0] | lines[1]) != 0;
+        let [low, high] = self.register_lines;
+        (if line(low) {1} else {0}) | (if line(high) {2} else {0})
+    }
+
+    fn prg_addr(&self, addr: Addr) -> usize {
+        let bank_count = self.prg_rom.len() / Self::PRG_BANK_SIZE;
+        let second_last = bank_count - 2;
+
+        let bank = match ((addr.0 - 0x8000) / 0x2000, self.prg_swap) {
+            (0, false) | (2, true) => self.prg_banks[0] as usize,
+            (0, true) | (2, false) => second_last,
+            (1, _) => self.prg_banks[1] as usize,
+            _ => bank_count - 1,
+        };
+        (bank % bank_count) * Self::PRG_BANK_SIZE + (addr.0 as usize % Self::PRG_BANK_SIZE)
+    }
+
+    fn chr_addr(&self, addr: Addr) -> usize {
+        let bank = (self.chr_banks[addr.0 as usize / Self::CHR_BANK_SIZE] >> self.chr_shift) as usize;
+        (bank * Self::CHR_BANK_SIZE + addr.0 as usize % Self::CHR_BANK_SIZE) % self.chr_rom.len()
+    }
+
+    /// CHR banks are written a nibble at a time, $B000/$B001 set the low and
+    /// high half of bank 0, $B002/$B003 bank 1 and so on up to $E003
+    fn set_chr_nibble(&mut self, addr: Addr, register: u8, value: u8) {
+        let index = ((addr.0 - 0xb000) / 0x1000 * 2) as usize + (register >> 1) as usize;
+        let bank = &mut self.chr_banks[index];
+        *bank = if register & 1 == 0 {
+            (*bank & 0x1f0) | (value as u16 & 0x0f)
+        } else {
+            (*bank & 0x00f) | ((value as u16 & 0x1f) << 4)
+        };
+    }
+}
+
+impl Mapper for VRC4 {
+    fn ppu_write(&mut self, addr: Addr, value: u8) -> anyhow::Result<()> {
+        if self.chr_writable {
+            let chr_addr = self.chr_addr(addr);
+            self.chr_rom[chr_addr] = value;
+        }
+        Ok(())
+    }
+
+    fn cpu_write(&mut self, addr: Addr, value: u8) -> anyhow::Result<()> {
+        if addr < 0x6000 {
+            anyhow::bail!("tried to write 0x{value:02x} outside pgm range: {addr}")
+        } else if addr < 0x8000 {
+            self.prg_ram[addr.0 as usize % 0x2000] = value;
+            return Ok(())
+        }
+
+        let register = self.register(addr);
+        match (addr.0 & 0xf000, register) {
+            (0x8000, _) => self.prg_banks[0] = value & 0x1f,
+            // VRC2 only has the lowest mirroring bit, which means the same thing
+            (0x9000, 0) => {
+                self.mirroring = match value & 0b11 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::OneScreenLower,
+                    _ => Mirroring::OneScreenUpper,
+                }
+            }
+            (0x9000, 2) => self.prg_swap = value & 0b10 != 0,
+            (0x9000, _) => {}
+            (0xa000, _) => self.prg_banks[1] = value & 0x1f,
+            (0xb000..=0xe000, _) => self.set_chr_nibble(addr, register, value),
+            (0xf000, 0) => self.irq.set_latch_low(value),
+            (0xf000, 1) => self.irq.set_latch_high(value),
+            (0xf000, 2) => self.irq.set_control(value),
+            (0xf000, _) => self.irq.acknowledge(),
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    fn ppu_peek(&self, addr: Addr) -> anyhow::Result<u8> {
+        Ok(self.chr_rom[self.chr_addr(addr)])
+    }
+
+    fn cpu_read(&self, addr: Addr) -> anyhow::Result<u8> {
+        if addr < 0x6000 {
+            anyhow::bail!("read outside pgm range: {addr}")
+        } else if addr < 0x8000 {
+            Ok(self.prg_ram[addr.0 as usize % 0x2000])
+        } else {
+            Ok(self.prg_rom[self.prg_addr(addr)])
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.irq.pending
+    }
+
+    fn mirroring(&self) -> Option<Mirroring> {
+        Some(self.mirroring)
+    }
+
+    fn tick(&mut self) {
+        self.irq.tick();
+    }
+}
+
+impl Display for VRC4 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("VRC2/VRC4")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::system::{addr::Addr, cart::Header};
+
+    use super::VRC4;
+
+    #[test]
+    fn address_lines_per_mapper() {
+        let addrs = [0xb001, 0xb002, 0xb004, 0xb008, 0xb040, 0xb080];
+        let expected = [
+            // A1/A2 or A6/A7
+            (21, [0, 1, 2, 0, 1, 2]),
+            // A1/A0
+            (22, [2, 1, 0, 0, 0, 0]),
+            // A0/A1 or A2/A3
+            (23, [1, 2, 1, 2, 0, 0]),
+            // A1/A0 or A3/A2
+            (25, [2, 1, 2, 1, 0, 0]),
+        ];
+
+        for (mapper_id, registers) in expected {
+            let mapper = VRC4::new(&Header::for_tests(mapper_id, 0x8000, 0x2000), vec![0; 0x8000], vec![0; 0x2000]);
+            let actual = addrs.map(|addr| mapper.register(Addr(addr)));
+            assert_eq!(actual, registers, "mapper {mapper_id}");
+        }
+    }
+}
@@ -0,0 +1,292 @@
+use std::fmt::Display;
+
+use crate::system::{addr::Addr, apu::mixer, bus::open_bus, cart::Header};
+
+use super::{Mapper, Mirroring, vrc_irq::VrcIrq};
+
+
+// INES 24 (VRC6a) and 26 (VRC6b)
+pub struct VRC6 {
+    pub(crate) prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    pub(crate) chr_rom: Vec<u8>,
+    /// VRC6b has A0 and A1 swapped
+    swapped_lines: bool,
+
+    /// 16 KB bank at $8000
+    prg_bank_16: u8,
+    /// 8 KB bank at $C000
+    prg_bank_8: u8,
+    chr_banks: [u8; 8],
+    /// $B003, only the mirroring and PRG RAM enable of the common 1 KB CHR
+    /// mode are supported
+    ppu_mode: u8,
+    irq: VrcIrq,
+
+    pulses: [Vrc6Pulse; 2],
+    sawtooth: Sawtooth,
+    /// $9003 bit 0 stops all three channels
+    audio_halted: bool,
+}
+
+impl VRC6 {
+    pub fn new(header: &Header, prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Self {
+        Self {
+            prg_rom,
+            prg_ram: vec![0; 0x2000],
+            chr_rom,
+            swapped_lines: header.mapper_id == 26,
+            prg_bank_16: 0,
+            prg_bank_8: 0,
+            chr_banks: [0; 8],
+            ppu_mode: 0,
+            irq: VrcIrq::default(),
+            pulses: [Vrc6Pulse::default(), Vrc6Pulse::default()],
+            sawtooth: Sawtooth::default(),
+            audio_halted: false,
+        }
+    }
+
+    fn register(&self, addr: Addr) -> u8 {
+        let register = (addr.0 & 0b11) as u8;
+        if self.swapped_lines {
+            ((register & 1) << 1) | (register >> 1)
+        } else {
+            register
+        }
+    }
+
+    fn prg_addr(&self, addr: Addr) -> usize {
+        let offset = addr.0 as usize - 0x8000;
+        let rom_addr = match offset / 0x2000 {
+            0 | 1 => self.prg_bank_16 as usize * 0x4000 + offset,
+            2 => self.prg_bank_8 as usize * 0x2000 + offset % 0x2000,
+            _ => self.prg_rom.len() - 0x2000 + offset % 0x2000,
+        };
+        rom_addr % self.prg_rom.len()
+    }
+
+    fn chr_addr(&self, addr: Addr) -> usize {
+        let bank = self.chr_banks[addr.0 as usize / 0x400] as usize;
+        (bank * 0x400 + addr.0 as usize % 0x400) % self.chr_rom.len()
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        self.ppu_mode & 0b1000_0000 != 0
+    }
+}
+
+impl Mapper for VRC6 {
+    fn ppu_write(&mut self, _addr: Addr, _value: u8) -> anyhow::Result<()> {
+        // CHR ROM
+        Ok(())
+    }
+
+    fn cpu_write(&mut self, addr: Addr, value: u8) -> anyhow::Result<()> {
+        if addr < 0x6000 {
+            anyhow::bail!("tried to write 0x{value:02x} outside pgm range: {addr}")
+        } else if addr < 0x8000 {
+            if self.prg_ram_enabled() {
+                self.prg_ram[addr.0 as usize - 0x6000] = value;
+            }
+            return Ok(())
+        }
+
+        let register = self.register(addr);
+        match (addr.0 & 0xf000, register) {
+            (0x8000, _) => self.prg_bank_16 = value,
+            (0x9000, 3) => self.audio_halted = value & 1 != 0,
+            (0x9000, _) => self.pulses[0].write(register, value),
+            (0xa000, 3) => {}
+            (0xa000, _) => self.pulses[1].write(register, value),
+            (0xb000, 3) => self.ppu_mode = value,
+            (0xb000, _) => self.sawtooth.write(register, value),
+            (0xc000, _) => self.prg_bank_8 = value,
+            (0xd000, _) => self.chr_banks[register as usize] = value,
+            (0xe000, _) => self.chr_banks[4 + register as usize] = value,
+            (0xf000, 0) => self.irq.set_latch(value),
+            (0xf000, 1) => self.irq.set_control(value),
+            (0xf000, 2) => self.irq.acknowledge(),
+            (0xf000, _) => {}
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    fn ppu_peek(&self, addr: Addr) -> anyhow::Result<u8> {
+        Ok(self.chr_rom[self.chr_addr(addr)])
+    }
+
+    fn cpu_read(&self, addr: Addr) -> anyhow::Result<u8> {
+        if addr < 0x6000 {
+            anyhow::bail!("read outside pgm range: {addr}")
+        } else if addr < 0x8000 {
+            // a disabled chip leaves the bus floating
+            let value = if self.prg_ram_enabled() {self.prg_ram[addr.0 as usize - 0x6000]} else {open_bus(addr)};
+            Ok(value)
+        } else {
+            Ok(self.prg_rom[self.prg_addr(addr)])
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.irq.pending
+    }
+
+    fn mirroring(&self) -> Option<Mirroring> {
+        Some(match (self.ppu_mode >> 2) & 0b11 {
+            0 => Mirroring::Vertical,
+            1 => Mirroring::Horizontal,
+            2 => Mirroring::OneScreenLower,
+            _ => Mirroring::OneScreenUpper,
+        })
+    }
+
+    fn tick(&mut self) {
+        self.irq.tick();
+
+        if !self.audio_halted {
+            self.pulses.iter_mut().for_each(Vrc6Pulse::clock);
+            self.sawtooth.clock();
+        }
+    }
+
+    fn audio_output(&self) -> f32 {
+        // all three channels sum on the same scale as the APU pulses
+        mixer::mix(self.pulses[0].output() + self.pulses[1].output(), self.sawtooth.output(), 0, 0, 0)
+    }
+}
+
+impl Display for VRC6 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("VRC6")
+    }
+}
+
+/// A pulse with 16 duty cycle steps and no envelope or length counter
+#[derive(Default)]
+struct Vrc6Pulse {
+    volume: u8,
+    duty: u8,
+    /// Ignores the duty and outputs the volume constantly
+    digitized: bool,
+    enabled: bool,
+    period: u16,
+    timer: u16,
+    step: u8,
+}
+
+impl Vrc6Pulse {
+    fn write(&mut self, register: u8, value: u8) {
+        match register {
+            0 => {
+                self.digitized = value & 0b1000_0000 != 0;
+                self.duty = (value >> 4) & 0b111;
+                self.volume = value & 0x0f;
+            }
+            1 => self.period = (self.period & 0x0f00) | value as u16,
+            _ => {
+                self.period = (self.period & 0x00ff) | ((value as u16 & 0x0f) << 8);
+                self.enabled = value & 0b1000_0000 != 0;
+                if !self.enabled {
+                    self.step = 15;
+                }
+            }
+        }
+    }
+
+    /// Clocked every CPU cycle
+    fn clock(&mut self) {
+        if !self.enabled {
+            return
+        }
+        if self.timer == 0 {
+            self.timer = self.period;
+            self.step = self.step.checked_sub(1).unwrap_or(15);
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.enabled && (self.digitized || self.step <= self.duty) {self.volume} else {0}
+    }
+}
+
+/// Adds the rate to an accumulator every other step and outputs its top
+/// 5 bits, resetting after 7 additions
+#[derive(Default)]
+struct Sawtooth {
+    rate: u8,
+    enabled: bool,
+    period: u16,
+    timer: u16,
+    step: u8,
+    accumulator: u8,
+}
+
+impl Sawtooth {
+    fn write(&mut self, register: u8, value: u8) {
+        match register {
+            0 => self.rate = value & 0x3f,
+            1 => self.period = (self.period & 0x0f00) | value as u16,
+            _ => {
+                self.period = (self.period & 0x00ff) | ((value as u16 & 0x0f) << 8);
+                self.enabled = value & 0b1000_0000 != 0;
+                if !self.enabled {
+                    self.step = 0;
+                    self.accumulator = 0;
+                }
+            }
+        }
+    }
+
+    /// Clocked every CPU cycle
+    fn clock(&mut self) {
+        if !self.enabled {
+            return
+        }
+        if self.timer > 0 {
+            self.timer -= 1;
+            return
+        }
+
+        self.timer = self.period;
+        self.step += 1;
+        if self.step == 14 {
+            self.step = 0;
+            self.accumulator = 0;
+        } else if self.step % 2 == 0 {
+            self.accumulator = self.accumulator.wrapping_add(self.rate);
+        }
+    }
+
+    fn output(&self) -> u8 {
+        self.accumulator >> 3
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{mappers::Mapper, system::{addr::Addr, cart::Header}};
+
+    use super::VRC6;
+
+    fn vrc6(mapper_id: u16) -> VRC6 {
+        VRC6::new(&Header::for_tests(mapper_id, 0x8000, 0x2000), vec![0; 0x8000], vec![0; 0x2000])
+    }
+
+    #[test]
+    fn vrc6b_swaps_a0_and_a1() {
+        let addrs = [0x9000, 0x9001, 0x9002, 0x9003];
+        assert_eq!(addrs.map(|addr| vrc6(24).register(Addr(addr))), [0, 1, 2, 3]);
+        assert_eq!(addrs.map(|addr| vrc6(26).register(Addr(addr))), [0, 2, 1, 3]);
+
+        // on VRC6b $9002 holds the low period bits and $9001 the high bits and enable
+        let mut mapper = vrc6(26);
+        mapper.cpu_write(Addr(0x9002), 0x34).unwrap();
+        mapper.cpu_write(Addr(0x9001), 0x81).unwrap();
+        assert_eq!(mapper.pulses[0].period, 0x134);
+        assert!(mapper.pulses[0].enabled);
+    }
+}
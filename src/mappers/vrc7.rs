@@ -0,0 +1,138 @@
+use std::fmt::Display;
+
+use crate::system::{addr::Addr, bus::open_bus, cart::Header};
+
+use super::{Mapper, Mirroring, vrc_irq::VrcIrq};
+
+
+// INES 85
+// The FM synthesis unit isn't emulated, writes to its registers are ignored
+pub struct VRC7 {
+    pub(crate) prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    pub(crate) chr_rom: Vec<u8>,
+    /// Carts without CHR ROM have 8 KB of CHR RAM instead
+    chr_writable: bool,
+
+    prg_banks: [u8; 3],
+    chr_banks: [u8; 8],
+    /// $E000: mirroring, PRG RAM enable and audio reset
+    control: u8,
+    irq: VrcIrq,
+}
+
+impl VRC7 {
+    const PRG_BANK_SIZE: usize = 0x2000;
+    const CHR_BANK_SIZE: usize = 0x0400;
+
+    pub fn new(header: &Header, prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Self {
+        Self {
+            prg_rom,
+            prg_ram: vec![0; 0x2000],
+            chr_rom,
+            chr_writable: header.chr_rom_size == 0,
+            prg_banks: [0; 3],
+            chr_banks: [0; 8],
+            control: 0,
+            irq: VrcIrq::default(),
+        }
+    }
+
+    fn prg_addr(&self, addr: Addr) -> usize {
+        let bank_count = self.prg_rom.len() / Self::PRG_BANK_SIZE;
+        let bank = match (addr.0 - 0x8000) as usize / Self::PRG_BANK_SIZE {
+            slot @ 0..=2 => self.prg_banks[slot] as usize,
+            _ => bank_count - 1,
+        };
+        (bank % bank_count) * Self::PRG_BANK_SIZE + (addr.0 as usize % Self::PRG_BANK_SIZE)
+    }
+
+    fn chr_addr(&self, addr: Addr) -> usize {
+        let bank = self.chr_banks[addr.0 as usize / Self::CHR_BANK_SIZE] as usize;
+        (bank * Self::CHR_BANK_SIZE + addr.0 as usize % Self::CHR_BANK_SIZE) % self.chr_rom.len()
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        self.control & 0b1000_0000 != 0
+    }
+}
+
+impl Mapper for VRC7 {
+    fn ppu_write(&mut self, addr: Addr, value: u8) -> anyhow::Result<()> {
+        if self.chr_writable {
+            let chr_addr = self.chr_addr(addr);
+            self.chr_rom[chr_addr] = value;
+        }
+        Ok(())
+    }
+
+    fn cpu_write(&mut self, addr: Addr, value: u8) -> anyhow::Result<()> {
+        if addr < 0x6000 {
+            anyhow::bail!("tried to write 0x{value:02x} outside pgm range: {addr}")
+        } else if addr < 0x8000 {
+            if self.prg_ram_enabled() {
+                self.prg_ram[addr.0 as usize - 0x6000] = value;
+            }
+            return Ok(())
+        }
+
+        // VRC7a selects the second register of each pair with A4, VRC7b with A3
+        let second = addr.0 & 0x18 != 0;
+        match (addr.0 & 0xf000, second) {
+            (0x8000, false) => self.prg_banks[0] = value & 0x3f,
+            (0x8000, true) => self.prg_banks[1] = value & 0x3f,
+            (0x9000, false) => self.prg_banks[2] = value & 0x3f,
+            // FM synthesis register select and data
+            (0x9000, true) => {}
+            (0xa000..=0xd000, _) => {
+                let index = ((addr.0 - 0xa000) / 0x1000 * 2) as usize + second as usize;
+                self.chr_banks[index] = value;
+            }
+            (0xe000, false) => self.control = value,
+            (0xe000, true) => self.irq.set_latch(value),
+            (0xf000, false) => self.irq.set_control(value),
+            (0xf000, true) => self.irq.acknowledge(),
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    fn ppu_peek(&self, addr: Addr) -> anyhow::Result<u8> {
+        Ok(self.chr_rom[self.chr_addr(addr)])
+    }
+
+    fn cpu_read(&self, addr: Addr) -> anyhow::Result<u8> {
+        if addr < 0x6000 {
+            anyhow::bail!("read outside pgm range: {addr}")
+        } else if addr < 0x8000 {
+            // a disabled chip leaves the bus floating
+            let value = if self.prg_ram_enabled() {self.prg_ram[addr.0 as usize - 0x6000]} else {open_bus(addr)};
+            Ok(value)
+        } else {
+            Ok(self.prg_rom[self.prg_addr(addr)])
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.irq.pending
+    }
+
+    fn mirroring(&self) -> Option<Mirroring> {
+        Some(match self.control & 0b11 {
+            0 => Mirroring::Vertical,
+            1 => Mirroring::Horizontal,
+            2 => Mirroring::OneScreenLower,
+            _ => Mirroring::OneScreenUpper,
+        })
+    }
+
+    fn tick(&mut self) {
+        self.irq.tick();
+    }
+}
+
+impl Display for VRC7 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("VRC7")
+    }
+}
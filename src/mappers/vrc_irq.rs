@@ -0,0 +1,115 @@
+/// The IRQ counter shared by the VRC4, VRC6 and VRC7. It counts CPU cycles,
+/// either directly or through a prescaler that approximates scanlines, and
+/// fires when the 8-bit counter overflows.
+#[derive(Default)]
+pub(crate) struct VrcIrq {
+    latch: u8,
+    counter: u8,
+    /// Counts down by 3 per CPU cycle from 341, the PPU dots on a line
+    prescaler: i16,
+    enabled: bool,
+    enable_after_ack: bool,
+    cycle_mode: bool,
+    pub(crate) pending: bool,
+}
+
+impl VrcIrq {
+    const DOTS_PER_LINE: i16 = 341;
+
+    /// VRC4 splits the latch across two registers
+    pub(crate) fn set_latch_low(&mut self, value: u8) {
+        self.latch = (self.latch & 0xf0) | (value & 0x0f);
+    }
+
+    pub(crate) fn set_latch_high(&mut self, value: u8) {
+        self.latch = (self.latch & 0x0f) | (value << 4);
+    }
+
+    pub(crate) fn set_latch(&mut self, value: u8) {
+        self.latch = value;
+    }
+
+    pub(crate) fn set_control(&mut self, value: u8) {
+        self.enable_after_ack = value & 0b001 != 0;
+        self.enabled = value & 0b010 != 0;
+        self.cycle_mode = value & 0b100 != 0;
+        self.pending = false;
+
+        if self.enabled {
+            self.counter = self.latch;
+            self.prescaler = Self::DOTS_PER_LINE;
+        }
+    }
+
+    pub(crate) fn acknowledge(&mut self) {
+        self.pending = false;
+        self.enabled = self.enable_after_ack;
+    }
+
+    /// Called once per CPU cycle
+    pub(crate) fn tick(&mut self) {
+        if !self.enabled {
+            return
+        }
+
+        if self.cycle_mode {
+            self.clock();
+        } else {
+            self.prescaler -= 3;
+            if self.prescaler <= 0 {
+                self.prescaler += Self::DOTS_PER_LINE;
+                self.clock();
+            }
+        }
+    }
+
+    fn clock(&mut self) {
+        if self.counter == 0xff {
+            self.counter = self.latch;
+            self.pending = true;
+        } else {
+            self.counter += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::VrcIrq;
+
+    #[test]
+    fn cycle_mode_fires_on_overflow() {
+        let mut irq = VrcIrq::default();
+        irq.set_latch(0xfd);
+        irq.set_control(0b111);
+
+        irq.tick();
+        irq.tick();
+        assert!(!irq.pending);
+        irq.tick();
+        assert!(irq.pending);
+
+        // reloaded from the latch, so it fires again 3 cycles later
+        irq.acknowledge();
+        (0..3).for_each(|_| irq.tick());
+        assert!(irq.pending);
+    }
+
+    #[test]
+    fn scanline_mode_counts_lines() {
+        let mut irq = VrcIrq::default();
+        irq.set_latch(0xfe);
+        irq.set_control(0b010);
+
+        // two lines of 113.67 CPU cycles each
+        (0..227).for_each(|_| irq.tick());
+        assert!(!irq.pending);
+        irq.tick();
+        assert!(irq.pending);
+
+        // acknowledging without the E bit disables the counter
+        irq.acknowledge();
+        (0..1000).for_each(|_| irq.tick());
+        assert!(!irq.pending);
+    }
+}
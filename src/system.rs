@@ -1,4 +1,4 @@
-use std::{io::{self, Read, Write as IOWrite}, fs, ops::Range};
+use std::{io::{self, Read, Write as IOWrite}, fs, ops::Range, path::Path};
 
 use self::{cpu::{CPU}, execution_state::ExecutionState, addr::Addr, options::Options};
 
@@ -26,29 +26,36 @@ pub struct System {
     pub cycles: u64,
     pub(crate) oam: [u8; 256],
     pub opts: Options,
+    /// NMI edge detector, set by the PPU
     pub(crate) nmi: bool,
+    /// Interrupts seen when polling at the start of the last CPU cycle, taken before the next instruction
+    pending_nmi: bool,
+    pending_irq: bool,
+    /// Set when the CPU executed a JAM opcode, it stays halted until reset
+    jammed: Option<ExecutionState>,
     history: Vec<ExecutionState>,
+    /// Where the next state is written, which is the oldest one once the history is full
     history_pos: usize,
-    pub offset: usize,
 }
 
 impl System {
     pub fn new(opts: Options) -> anyhow::Result<Self> {
         let cpu = CPU::init();
-        let history_pos = opts.history_len-1;
         Ok(System {
             ram: vec![0; 2048],
             ppu: ppu::PPU::init(),
-            apu: apu::APU::init(),
+            apu: apu::APU::init(opts.sample_rate),
             cpu,
             cart: Cart::empty()?,
             cycles: 7,
             oam: [0u8; 256],
             opts,
             nmi: false,
+            pending_nmi: false,
+            pending_irq: false,
+            jammed: None,
             history: Vec::new(),
-            history_pos,
-            offset: 1016,
+            history_pos: 0,
         })
     }
 
@@ -71,7 +78,7 @@ impl System {
     }
 
     fn dump_ppu_mem(&self, range: Range<u16>) {
-        let bytes: Vec<u8> = range.map(|a| self.cart.mapper.ppu_read(Addr(a)).unwrap()).collect();
+        let bytes: Vec<u8> = range.map(|a| self.cart.mapper.ppu_peek(Addr(a)).unwrap()).collect();
         dump_mem(bytes.iter(), None).expect("failed to dump PPU memory");
     }
 
@@ -110,13 +117,22 @@ impl System {
     }
 
     pub fn dump_history(&self) {
-        for (i, hi) in (self.history_pos..self.opts.history_len).chain(0..self.history_pos).enumerate() {
-            if hi >= self.history.len() {break}
-            let state = &self.history[hi];
-            eprintln!("[{:3}] {state}", 1isize - (self.opts.history_len - i) as isize);
+        let count = self.history.len();
+        for (i, state) in self.history().enumerate() {
+            eprintln!("[{:3}] {state}", 1isize - (count - i) as isize);
         }
     }
 
+    /// The last executed instructions, oldest first, as many as `Options::history_len` keeps
+    pub fn history(&self) -> impl Iterator<Item = &ExecutionState> {
+        self.history[self.history_pos..].iter().chain(self.history[..self.history_pos].iter())
+    }
+
+    /// The address of the JAM opcode the CPU is halted on, if any
+    pub fn jammed_at(&self) -> Option<Addr> {
+        self.jammed.as_ref().map(|state| state.cpu.pc)
+    }
+
     pub fn load_cart(&mut self, cart_file: &fs::File) -> Result<()> {
         let reader = io::BufReader::new(cart_file);
 
@@ -146,10 +162,39 @@ impl System {
         Ok(())
     }
 
+    /// Starts recording the audio output to a WAV file, optionally with a file per channel next to it
+    pub fn record_audio<P: AsRef<Path>>(&mut self, path: P, sample_rate: u32, stems: bool) -> Result<()> {
+        self.stop_recording()?;
+        self.apu.recorder = Some(apu::recorder::Recorder::create(path.as_ref(), sample_rate, stems)?);
+        Ok(())
+    }
+
+    /// Replaces the colors used for the frame buffer, see [`ppu::palette`]
+    pub fn set_palette(&mut self, colors: Vec<u32>) -> Result<()> {
+        if colors.len() != 512 {
+            anyhow::bail!("palette has {} colors instead of 512", colors.len());
+        }
+        self.ppu.colors = colors;
+        Ok(())
+    }
+
+    /// The 512 colors used for the frame buffer
+    pub fn palette(&self) -> &[u32] {
+        &self.ppu.colors
+    }
+
+    /// Finishes the current audio recording, if any
+    pub fn stop_recording(&mut self) -> Result<()> {
+        if let Some(recorder) = self.apu.recorder.take() {
+            recorder.finish()?;
+        }
+        Ok(())
+    }
+
     pub fn reset(&mut self) -> Result<()> {
-        // Read reset vector
-        let rv = self.read_addr(0xfffc)?;
-        self.cpu.pc = rv;
+        // Read reset vector, the reset sequence is already accounted for in the initial cycle count
+        self.cpu.pc = Addr::from_bytes(self.peek_byte(0xfffd), self.peek_byte(0xfffc));
+        self.jammed = None;
         eprintln!("Resetting to {}", self.cpu.pc);
         Ok(())
     }
@@ -164,20 +209,28 @@ impl System {
 
         loop {
 
-            if self.nmi {
-                CPU::stack_push_word(self, self.cpu.pc.into())?;
-                CPU::stack_push_byte(self, self.cpu.status())?;
+            let scan_row_before = self.ppu.scan_row;
 
-                let nmi_handler_addr = self.read_addr(0xfffa)?;
-                self.cpu.pc = nmi_handler_addr;
+            if self.jammed.is_some() {
+                // The CPU is stuck, but the rest of the system keeps running
+                self.tick_cycle()?;
+                if scan_row_before < 241 && self.ppu.scan_row >= 241 {
+                    return Ok(self.jammed.clone().unwrap());
+                }
+                continue;
+            }
 
-                // eprintln!("NMI! => {nmi_handler_addr}");
-                
+            if std::mem::take(&mut self.pending_nmi) {
                 self.nmi = false;
+                self.service_interrupt(CPU::ADDR_NMI)?;
+            } else if std::mem::take(&mut self.pending_irq) {
+                self.service_interrupt(CPU::ADDR_IRQ)?;
             }
 
 
             let cpu = self.cpu.clone();
+            let cycles = self.cycles;
+            let ppu = (self.ppu.scan_row, self.ppu.scan_line);
             let (op, am) = cpu::load(self)?;
 
             let byte_count = am.bytes() + 1;
@@ -187,8 +240,8 @@ impl System {
                     cpu, 
                     pc_bytes, 
                     am: am.clone(), 
-                    cycles: self.cycles,
-                    ppu: (self.ppu.scan_row, self.ppu.scan_line)
+                    cycles,
+                    ppu,
             };
 
             if self.opts.dump_ops {
@@ -199,26 +252,18 @@ impl System {
             if self.opts.history_len > 0 {
                 if self.history.len() < self.opts.history_len {
                     self.history.push(actual.clone());
-                    self.history_pos = self.history.len() - 1;
                 } else {
                     self.history[self.history_pos] = actual.clone();
-                    self.history_pos += 1;
-                    if self.history_pos == self.opts.history_len {
-                        self.history_pos = 0;
-                    }   
-                    
                 }
+                self.history_pos = (self.history_pos + 1) % self.opts.history_len;
             }
 
       
 
-            let cpu_cycles = op.execute(self, &am)?;
-            let ppu_cycles = cpu_cycles * 3;
+            op.execute(self, &am)?;
 
-            let scan_row_before = self.ppu.scan_row;
-
-            for _ in 0..ppu_cycles {
-                ppu::tick(self)?;
+            if matches!(op, cpu::opcode::OpCode::Jam) {
+                self.jammed = Some(actual.clone());
             }
 
             if scan_row_before < 241 && self.ppu.scan_row >= 241 {
@@ -244,6 +289,39 @@ impl System {
 
     }
 
+    /// The level-triggered IRQ line, held low for as long as any source asserts it
+    pub fn irq_line(&self) -> bool {
+        self.apu.irq() || self.cart.mapper.irq()
+    }
+
+    /// Takes an NMI or IRQ, which costs the same 7 cycles as BRK
+    fn service_interrupt(&mut self, vector: Addr) -> Result<()> {
+        CPU::interrupt(self, vector, false)
+    }
+
+    /// Advances the PPU by three dots and the APU by one cycle, done on every CPU bus access.
+    /// Also runs the cycles the CPU gets stalled for by DMC fetches.
+    pub(crate) fn tick_cycle(&mut self) -> Result<()> {
+        // The CPU polls interrupts at the end of each cycle, so after an instruction this holds what was seen at the end of its penultimate cycle
+        self.pending_nmi = self.nmi;
+        self.pending_irq = self.irq_line() && !self.cpu.interrupt;
+
+        let mut cycles = 1;
+        while cycles > 0 {
+            cycles -= 1;
+            self.cycles += 1;
+
+            for _ in 0..3 {
+                ppu::tick(self)?;
+            }
+            apu::tick(self)?;
+            self.cart.mapper.tick();
+
+            cycles += std::mem::take(&mut self.apu.stall_cycles);
+        }
+        Ok(())
+    }
+
     pub fn print_stack(&mut self) -> Result<()> {
         let mut stderr = tc::StandardStream::stderr(tc::ColorChoice::AlwaysAnsi);
         
@@ -274,7 +352,7 @@ impl System {
                 if self.cpu.sp == addr {
                     stderr.set_color(ColorSpec::new().set_fg(Some(Color::Green)).set_intense(true))?;
                 }
-                write!(&mut stderr, "{:02x} ", self.read_byte(CPU::addr_stack(addr))?)?;
+                write!(&mut stderr, "{:02x} ", self.peek_byte(CPU::addr_stack(addr)))?;
 
                 if self.cpu.sp == addr {
                     stderr.set_color(&ColorSpec::new())?;
@@ -10,9 +10,40 @@ $4017	    All	        Frame counter
 */
 
 use super::System;
+use pulse::{Pulse, PulseChannel};
+use triangle::Triangle;
+use noise::Noise;
+use dmc::Dmc;
+use frame_counter::{FrameCounter, FrameClocks};
+use resampler::Resampler;
+use recorder::Recorder;
+
+pub mod envelope;
+pub mod length_counter;
+pub mod pulse;
+pub mod triangle;
+pub mod noise;
+pub mod dmc;
+pub mod frame_counter;
+pub mod mixer;
+pub mod resampler;
+pub mod recorder;
 
 pub struct APU {
-    pub mem: Vec<u8>,
+    pub pulse1: Pulse,
+    pub pulse2: Pulse,
+    pub triangle: Triangle,
+    pub noise: Noise,
+    pub dmc: Dmc,
+    pub frame_counter: FrameCounter,
+    /// CPU cycles the CPU has to be stalled for, due to DMC sample fetches
+    pub(crate) stall_cycles: u64,
+    /// CPU cycles since power-up, the APU runs at half the CPU rate
+    cycles: u64,
+    /// Converts the mixed output to the host sample rate, if audio output is enabled
+    resampler: Option<Resampler>,
+    samples: Vec<f32>,
+    pub(crate) recorder: Option<Recorder>,
     pub polling_controller: bool,
     pub polling_expansion: bool,
     pub controller1: ControllerState,
@@ -91,10 +122,22 @@ impl ControllerState {
 
 fn mask(index: u8) -> u8 {1 << index}
 
+const MAX_BUFFERED_SAMPLES: usize = 48000;
+
 impl APU {
-    pub fn init() -> Self {
+    pub fn init(sample_rate: Option<u32>) -> Self {
         Self {
-            mem: vec![0; 0x18],
+            pulse1: Pulse::new(PulseChannel::One),
+            pulse2: Pulse::new(PulseChannel::Two),
+            triangle: Triangle::default(),
+            noise: Noise::default(),
+            dmc: Dmc::default(),
+            frame_counter: FrameCounter::default(),
+            stall_cycles: 0,
+            cycles: 0,
+            resampler: sample_rate.map(Resampler::new),
+            samples: Vec::new(),
+            recorder: None,
             polling_controller: false,
             polling_expansion: false,
             controller1: ControllerState{buttons: 0, step: 0},
@@ -109,20 +152,148 @@ impl APU {
             cid => panic!("invalid controller: {cid}")
         }
     }
+
+    fn clock_quarter_frame(&mut self) {
+        self.pulse1.clock_quarter_frame();
+        self.pulse2.clock_quarter_frame();
+        self.triangle.clock_quarter_frame();
+        self.noise.clock_quarter_frame();
+    }
+
+    fn clock_half_frame(&mut self) {
+        self.pulse1.clock_half_frame();
+        self.pulse2.clock_half_frame();
+        self.triangle.clock_half_frame();
+        self.noise.clock_half_frame();
+    }
+
+    /// The current output level of each channel, in the order of `recorder::STEM_NAMES`
+    pub fn channel_outputs(&self) -> [u8; 5] {
+        [
+            self.pulse1.output(),
+            self.pulse2.output(),
+            self.triangle.output(),
+            self.noise.output(),
+            self.dmc.output(),
+        ]
+    }
+
+    /// The current mixed output of all channels, between 0.0 and 1.0
+    pub fn output(&self) -> f32 {
+        let [pulse1, pulse2, triangle, noise, dmc] = self.channel_outputs();
+        mixer::mix(pulse1, pulse2, triangle, noise, dmc)
+    }
+
+    /// Takes the samples generated since the last call, at the sample rate given in `Options`
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        std::mem::take(&mut self.samples)
+    }
+
+    /// Fine-tunes the output sample rate, see `Resampler::set_rate_adjust`
+    pub fn set_rate_adjust(&mut self, ratio: f64) {
+        if let Some(resampler) = &mut self.resampler {
+            resampler.set_rate_adjust(ratio);
+        }
+    }
+
+    /// The APU's contribution to the CPU IRQ line
+    pub fn irq(&self) -> bool {
+        self.frame_counter.interrupt || self.dmc.interrupt
+    }
+
+    fn status(&self) -> u8 {
+        0
+        | if self.pulse1.length.active() {1 << 0} else {0}
+        | if self.pulse2.length.active() {1 << 1} else {0}
+        | if self.triangle.length.active() {1 << 2} else {0}
+        | if self.noise.length.active() {1 << 3} else {0}
+        | if self.dmc.active() {1 << 4} else {0}
+        | if self.frame_counter.interrupt {1 << 6} else {0}
+        | if self.dmc.interrupt {1 << 7} else {0}
+    }
+
+    fn set_enabled(&mut self, value: u8) {
+        self.pulse1.length.set_enabled(value & (1 << 0) != 0);
+        self.pulse2.length.set_enabled(value & (1 << 1) != 0);
+        self.triangle.length.set_enabled(value & (1 << 2) != 0);
+        self.noise.length.set_enabled(value & (1 << 3) != 0);
+        self.dmc.set_enabled(value & (1 << 4) != 0);
+    }
+}
+
+/// Advances the APU by one CPU cycle
+pub(crate) fn tick(sys: &mut System) -> anyhow::Result<()> {
+    let apu = &mut sys.apu;
+
+    let FrameClocks { quarter, half } = apu.frame_counter.clock();
+    if quarter {
+        apu.clock_quarter_frame();
+    }
+    if half {
+        apu.clock_half_frame();
+    }
+
+    apu.triangle.clock_timer();
+    apu.noise.clock_timer();
+    apu.dmc.clock_timer();
+
+    if apu.cycles % 2 == 1 {
+        apu.pulse1.clock_timer();
+        apu.pulse2.clock_timer();
+    }
+
+    apu.cycles += 1;
+
+    let outputs = apu.channel_outputs();
+    let expansion = sys.cart.mapper.audio_output();
+    let output = apu.output() + expansion;
+    if let Some(sample) = apu.resampler.as_mut().and_then(|r| r.push(output)) {
+        // Don't let the buffer grow forever if nobody is taking the samples
+        if apu.samples.len() >= MAX_BUFFERED_SAMPLES {
+            apu.samples.clear();
+        }
+        apu.samples.push(sample);
+    }
+
+    if let Some(recorder) = &mut apu.recorder {
+        recorder.push(outputs, expansion)?;
+    }
+
+    // The DMC memory reader fetches through the CPU bus, stalling the CPU
+    if let Some(addr) = sys.apu.dmc.fetch_addr() {
+        let value = sys.bus_read(addr)?;
+        sys.apu.dmc.fill_buffer(value);
+        sys.apu.stall_cycles += dmc::DMA_STALL_CYCLES;
+    }
+
+    Ok(())
 }
 
 pub fn read(sys: &mut System, addr: u8) -> u8 {
     // eprintln!("Read from APU ${:02x}", addr);
     match addr {
+        0x15 => {
+            let value = sys.apu.status();
+            // Reading the status clears the frame interrupt
+            sys.apu.frame_counter.interrupt = false;
+            value
+        }
         0x16 => if sys.apu.controller1.poll(sys.apu.polling_controller) {0x41} else {0x40},
         0x17 => if sys.apu.controller2.poll(sys.apu.polling_controller) {0x41} else {0x40},
 
-        _ => sys.apu.mem[addr as usize],
+        // Write-only registers read back as open bus, which is usually the high byte of the address
+        _ => 0x40,
     }
 }
 
 pub(crate) fn write(sys: &mut System, addr: u8, value: u8) {
     match addr {
+        0x00..=0x03 => sys.apu.pulse1.write(addr, value),
+        0x04..=0x07 => sys.apu.pulse2.write(addr - 0x04, value),
+        0x08..=0x0b => sys.apu.triangle.write(addr - 0x08, value),
+        0x0c..=0x0f => sys.apu.noise.write(addr - 0x0c, value),
+        0x10..=0x13 => sys.apu.dmc.write(addr - 0x10, value),
+        0x15 => sys.apu.set_enabled(value),
         0x16 => {
             // eprintln!("POLLING CONTROLLER! {:08b} {:08b}", value, sys.apu.controller1.buttons);
             sys.apu.polling_controller = (value & 1) != 0;
@@ -133,6 +304,10 @@ pub(crate) fn write(sys: &mut System, addr: u8, value: u8) {
                 sys.apu.controller2.step = 0;
             }
         }
-        _ => sys.apu.mem[addr as usize] = value
+        0x17 => {
+            let odd_cycle = sys.apu.cycles % 2 == 1;
+            sys.apu.frame_counter.write(value, odd_cycle);
+        }
+        _ => unreachable!("invalid APU register {addr:02x}")
     }
 }
\ No newline at end of file
@@ -0,0 +1,164 @@
+/*
+$4010	IL-- RRRR	IRQ enable (I), loop (L), frequency (R)
+$4011	-DDD DDDD	Load counter (D)
+$4012	AAAA AAAA	Sample address (A), $C000 + A * 64
+$4013	LLLL LLLL	Sample length (L), L * 16 + 1 bytes
+*/
+
+use crate::system::addr::Addr;
+
+/// Timer periods in CPU cycles (NTSC)
+const RATE_TABLE: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+
+/// CPU cycles lost each time the memory reader fetches a sample byte
+pub const DMA_STALL_CYCLES: u64 = 4;
+
+pub struct Dmc {
+    irq_enabled: bool,
+    looping: bool,
+    timer_period: u16,
+    timer: u16,
+    pub(crate) interrupt: bool,
+
+    // Memory reader
+    sample_address: u16,
+    sample_length: u16,
+    current_address: u16,
+    bytes_remaining: u16,
+    sample_buffer: Option<u8>,
+
+    // Output unit
+    shift: u8,
+    bits_remaining: u8,
+    silence: bool,
+    level: u8,
+}
+
+impl Default for Dmc {
+    fn default() -> Self {
+        Self {
+            irq_enabled: false,
+            looping: false,
+            timer_period: RATE_TABLE[0] - 1,
+            timer: 0,
+            interrupt: false,
+            sample_address: 0xc000,
+            sample_length: 1,
+            current_address: 0xc000,
+            bytes_remaining: 0,
+            sample_buffer: None,
+            shift: 0,
+            bits_remaining: 8,
+            silence: true,
+            level: 0,
+        }
+    }
+}
+
+impl Dmc {
+    pub fn write(&mut self, reg: u8, value: u8) {
+        match reg {
+            0 => {
+                self.irq_enabled = value & 0b1000_0000 != 0;
+                self.looping = value & 0b0100_0000 != 0;
+                self.timer_period = RATE_TABLE[(value & 0b1111) as usize] - 1;
+                if !self.irq_enabled {
+                    self.interrupt = false;
+                }
+            }
+            1 => {
+                self.level = value & 0b0111_1111;
+            }
+            2 => {
+                self.sample_address = 0xc000 | ((value as u16) << 6);
+            }
+            3 => {
+                self.sample_length = ((value as u16) << 4) | 1;
+            }
+            _ => unreachable!("invalid DMC register {reg}")
+        }
+    }
+
+    /// Handles the DMC bit of a $4015 write
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.interrupt = false;
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+
+    fn restart(&mut self) {
+        self.current_address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    pub fn active(&self) -> bool {
+        self.bytes_remaining > 0
+    }
+
+    /// The address the memory reader wants to fetch, if the sample buffer needs refilling
+    pub fn fetch_addr(&self) -> Option<Addr> {
+        if self.sample_buffer.is_none() && self.bytes_remaining > 0 {
+            Some(Addr(self.current_address))
+        } else {
+            None
+        }
+    }
+
+    /// Completes a memory reader fetch started by `fetch_addr`
+    pub fn fill_buffer(&mut self, value: u8) {
+        self.sample_buffer = Some(value);
+
+        // The address wraps around to $8000, not $0000
+        self.current_address = if self.current_address == 0xffff {0x8000} else {self.current_address + 1};
+        self.bytes_remaining -= 1;
+
+        if self.bytes_remaining == 0 {
+            if self.looping {
+                self.restart();
+            } else if self.irq_enabled {
+                self.interrupt = true;
+            }
+        }
+    }
+
+    /// Clocked every CPU cycle
+    pub fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.timer_period;
+
+        if !self.silence {
+            if self.shift & 1 != 0 {
+                if self.level <= 125 {
+                    self.level += 2;
+                }
+            } else if self.level >= 2 {
+                self.level -= 2;
+            }
+        }
+        self.shift >>= 1;
+
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.sample_buffer.take() {
+                Some(sample) => {
+                    self.silence = false;
+                    self.shift = sample;
+                }
+                None => self.silence = true,
+            }
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        self.level
+    }
+}
@@ -0,0 +1,46 @@
+/*
+The envelope unit is shared by the pulse and noise channels. It either outputs
+a constant volume, or a decaying sawtooth that can optionally loop.
+
+Clocked by the quarter-frame signal from the frame sequencer.
+*/
+
+#[derive(Default)]
+pub struct Envelope {
+    pub(crate) start: bool,
+    pub(crate) looping: bool,
+    pub(crate) constant: bool,
+    pub(crate) volume: u8,
+    divider: u8,
+    decay: u8,
+}
+
+impl Envelope {
+    /// Handles the lower 6 bits of $4000/$4004/$400C (--LC VVVV)
+    pub fn write(&mut self, value: u8) {
+        self.looping  = value & 0b0010_0000 != 0;
+        self.constant = value & 0b0001_0000 != 0;
+        self.volume   = value & 0b0000_1111;
+    }
+
+    pub fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = self.volume;
+        } else if self.divider == 0 {
+            self.divider = self.volume;
+            if self.decay > 0 {
+                self.decay -= 1;
+            } else if self.looping {
+                self.decay = 15;
+            }
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        if self.constant {self.volume} else {self.decay}
+    }
+}
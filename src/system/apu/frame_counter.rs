@@ -0,0 +1,140 @@
+/*
+$4017	MI-- ----	Mode (M, 0 = 4-step, 1 = 5-step), IRQ inhibit flag (I)
+
+mode 0:    mode 1:       function
+---------  -----------  -----------------------------
+ - - - f    - - - - -    IRQ (if bit 6 is clear)
+ - l - l    - l - - l    Length counter and sweep
+ e e e e    e e e - e    Envelope and linear counter
+*/
+
+/// Step timings in CPU cycles (NTSC)
+const STEP_1: u32 = 7457;
+const STEP_2: u32 = 14913;
+const STEP_3: u32 = 22371;
+const STEP_4: u32 = 29829;
+const STEP_5: u32 = 37281;
+
+#[derive(Default)]
+pub struct FrameCounter {
+    five_step: bool,
+    irq_inhibit: bool,
+    pub(crate) interrupt: bool,
+    cycle: u32,
+    /// CPU cycles left until a $4017 write takes effect
+    reset_delay: Option<u8>,
+}
+
+/// The clock signals sent to the channels on a frame counter step
+pub struct FrameClocks {
+    pub quarter: bool,
+    pub half: bool,
+}
+
+impl FrameClocks {
+    const NONE: Self = Self { quarter: false, half: false };
+    const QUARTER: Self = Self { quarter: true, half: false };
+    const HALF: Self = Self { quarter: true, half: true };
+}
+
+impl FrameCounter {
+    /// `odd_cycle` tells whether the write happens between two APU cycles, which delays the reset by a CPU cycle
+    pub fn write(&mut self, value: u8, odd_cycle: bool) {
+        self.five_step = value & 0b1000_0000 != 0;
+        self.irq_inhibit = value & 0b0100_0000 != 0;
+        if self.irq_inhibit {
+            self.interrupt = false;
+        }
+        self.reset_delay = Some(if odd_cycle {4} else {3});
+    }
+
+    /// Clocked every CPU cycle
+    pub fn clock(&mut self) -> FrameClocks {
+        if let Some(delay) = self.reset_delay {
+            if delay <= 1 {
+                self.reset_delay = None;
+                self.cycle = 0;
+                // Entering 5-step mode immediately clocks all units
+                return if self.five_step {FrameClocks::HALF} else {FrameClocks::NONE};
+            }
+            self.reset_delay = Some(delay - 1);
+        }
+
+        self.cycle += 1;
+
+        if self.five_step {
+            match self.cycle {
+                STEP_1 | STEP_3 => FrameClocks::QUARTER,
+                STEP_2 => FrameClocks::HALF,
+                STEP_5 => FrameClocks::HALF,
+                c if c > STEP_5 => {
+                    self.cycle = 0;
+                    FrameClocks::NONE
+                }
+                _ => FrameClocks::NONE,
+            }
+        } else {
+            match self.cycle {
+                STEP_1 | STEP_3 => FrameClocks::QUARTER,
+                STEP_2 => FrameClocks::HALF,
+                // The IRQ flag is raised over three consecutive cycles
+                c if c == STEP_4 - 1 => {
+                    self.raise_interrupt();
+                    FrameClocks::NONE
+                }
+                STEP_4 => {
+                    self.raise_interrupt();
+                    FrameClocks::HALF
+                }
+                c if c > STEP_4 => {
+                    self.raise_interrupt();
+                    self.cycle = 0;
+                    FrameClocks::NONE
+                }
+                _ => FrameClocks::NONE,
+            }
+        }
+    }
+
+    fn raise_interrupt(&mut self) {
+        if !self.irq_inhibit {
+            self.interrupt = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FrameCounter, FrameClocks, STEP_4, STEP_5};
+
+    fn run_sequence(fc: &mut FrameCounter, cycles: u32) -> (usize, usize) {
+        let (mut quarters, mut halves) = (0, 0);
+        for _ in 0..cycles {
+            let FrameClocks { quarter, half } = fc.clock();
+            if quarter { quarters += 1 }
+            if half { halves += 1 }
+        }
+        (quarters, halves)
+    }
+
+    #[test]
+    fn four_step_mode_raises_interrupt() {
+        let mut fc = FrameCounter::default();
+        assert_eq!(run_sequence(&mut fc, STEP_4 + 1), (4, 2));
+        assert!(fc.interrupt);
+
+        let mut fc = FrameCounter::default();
+        fc.write(0b0100_0000, false);
+        run_sequence(&mut fc, STEP_4 + 4);
+        assert!(!fc.interrupt);
+    }
+
+    #[test]
+    fn five_step_mode_never_raises_interrupt() {
+        let mut fc = FrameCounter::default();
+        fc.write(0b1000_0000, false);
+        // The immediate clock from the write, and one full sequence
+        assert_eq!(run_sequence(&mut fc, STEP_5 + 3), (5, 3));
+        assert!(!fc.interrupt);
+    }
+}
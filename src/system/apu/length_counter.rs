@@ -0,0 +1,42 @@
+/*
+The length counter silences a channel after a set amount of half-frame clocks.
+Used by all channels except the DMC.
+*/
+
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20,  2, 40,  4, 80,  6, 160,  8, 60, 10, 14, 12, 26, 14,
+    12,  16, 24, 18, 48, 20, 96, 22, 192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+#[derive(Default)]
+pub struct LengthCounter {
+    pub(crate) halt: bool,
+    enabled: bool,
+    counter: u8,
+}
+
+impl LengthCounter {
+    /// Loads the counter from the upper 5 bits of the channel's last register (LLLL L---)
+    pub fn load(&mut self, value: u8) {
+        if self.enabled {
+            self.counter = LENGTH_TABLE[(value >> 3) as usize];
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.counter = 0;
+        }
+    }
+
+    pub fn clock(&mut self) {
+        if !self.halt && self.counter > 0 {
+            self.counter -= 1;
+        }
+    }
+
+    pub fn active(&self) -> bool {
+        self.counter > 0
+    }
+}
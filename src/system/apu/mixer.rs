@@ -0,0 +1,26 @@
+/*
+Combines the channel outputs the same way the NES does, using the
+non-linear approximations from https://www.nesdev.org/wiki/APU_Mixer
+
+pulse_out = 95.88 / ((8128 / (pulse1 + pulse2)) + 100)
+tnd_out   = 159.79 / ((1 / ((triangle / 8227) + (noise / 12241) + (dmc / 22638))) + 100)
+*/
+
+/// Mixes the channel outputs into a sample between 0.0 and 1.0
+pub fn mix(pulse1: u8, pulse2: u8, triangle: u8, noise: u8, dmc: u8) -> f32 {
+    let pulse_sum = (pulse1 + pulse2) as f32;
+    let pulse_out = if pulse_sum == 0.0 {
+        0.0
+    } else {
+        95.88 / ((8128.0 / pulse_sum) + 100.0)
+    };
+
+    let tnd_sum = (triangle as f32 / 8227.0) + (noise as f32 / 12241.0) + (dmc as f32 / 22638.0);
+    let tnd_out = if tnd_sum == 0.0 {
+        0.0
+    } else {
+        159.79 / ((1.0 / tnd_sum) + 100.0)
+    };
+
+    pulse_out + tnd_out
+}
@@ -0,0 +1,112 @@
+/*
+$400C	--LC VVVV	Envelope loop / length counter halt (L), constant volume (C), volume/envelope (V)
+$400D	---- ----	Unused
+$400E	M--- PPPP	Mode flag (M), noise period (P)
+$400F	LLLL L---	Length counter load (L)
+*/
+
+use super::{envelope::Envelope, length_counter::LengthCounter};
+
+/// Timer periods in CPU cycles (NTSC)
+const PERIOD_TABLE: [u16; 16] = [
+    4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+];
+
+pub struct Noise {
+    pub(crate) envelope: Envelope,
+    pub(crate) length: LengthCounter,
+    mode: bool,
+    shift: u16,
+    timer_period: u16,
+    timer: u16,
+}
+
+impl Default for Noise {
+    fn default() -> Self {
+        Self {
+            envelope: Envelope::default(),
+            length: LengthCounter::default(),
+            mode: false,
+            // The shift register is 1 on power-up
+            shift: 1,
+            timer_period: PERIOD_TABLE[0] - 1,
+            timer: 0,
+        }
+    }
+}
+
+impl Noise {
+    pub fn write(&mut self, reg: u8, value: u8) {
+        match reg {
+            0 => {
+                self.length.halt = value & 0b0010_0000 != 0;
+                self.envelope.write(value);
+            }
+            1 => {}
+            2 => {
+                self.mode = value & 0b1000_0000 != 0;
+                self.timer_period = PERIOD_TABLE[(value & 0b1111) as usize] - 1;
+            }
+            3 => {
+                self.length.load(value);
+                self.envelope.start = true;
+            }
+            _ => unreachable!("invalid noise register {reg}")
+        }
+    }
+
+    /// Clocked every CPU cycle
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            // Mode 1 taps bit 6 instead of bit 1, giving a short 93-step sequence
+            let tap = if self.mode {6} else {1};
+            let feedback = (self.shift ^ (self.shift >> tap)) & 1;
+            self.shift = (self.shift >> 1) | (feedback << 14);
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn clock_quarter_frame(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn clock_half_frame(&mut self) {
+        self.length.clock();
+    }
+
+    pub fn output(&self) -> u8 {
+        if !self.length.active() || self.shift & 1 != 0 {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Noise;
+
+    fn sequence_length(mode: u8) -> usize {
+        let mut noise = Noise::default();
+        noise.write(2, mode);
+        let start = noise.shift;
+        let mut steps = 0;
+        loop {
+            noise.timer = 0;
+            noise.clock_timer();
+            steps += 1;
+            if noise.shift == start {
+                return steps;
+            }
+        }
+    }
+
+    #[test]
+    fn lfsr_sequence_lengths() {
+        assert_eq!(sequence_length(0b0000_0000), 32767);
+        assert_eq!(sequence_length(0b1000_0000), 93);
+    }
+}
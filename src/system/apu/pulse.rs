@@ -0,0 +1,199 @@
+/*
+$4000 / $4004	DDLC VVVV	Duty (D), envelope loop / length counter halt (L), constant volume (C), volume/envelope (V)
+$4001 / $4005	EPPP NSSS	Sweep unit: enabled (E), period (P), negate (N), shift (S)
+$4002 / $4006	TTTT TTTT	Timer low (T)
+$4003 / $4007	LLLL LTTT	Length counter load (L), timer high (T)
+*/
+
+use super::{envelope::Envelope, length_counter::LengthCounter};
+
+const DUTY_TABLE: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0], // 12.5%
+    [0, 1, 1, 0, 0, 0, 0, 0], // 25%
+    [0, 1, 1, 1, 1, 0, 0, 0], // 50%
+    [1, 0, 0, 1, 1, 1, 1, 1], // 25% negated
+];
+
+/// The two pulse channels only differ in how the sweep unit negates the period
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PulseChannel {
+    /// Pulse 1 uses ones' complement, subtracting one extra
+    One,
+    /// Pulse 2 uses two's complement
+    Two,
+    /// The extra pulses on mapper chips like the MMC5, which have no sweep
+    /// unit and so never mute
+    Expansion,
+}
+
+pub struct Pulse {
+    channel: PulseChannel,
+    pub(crate) envelope: Envelope,
+    pub(crate) length: LengthCounter,
+    sweep: Sweep,
+    duty: u8,
+    sequence_pos: u8,
+    timer_period: u16,
+    timer: u16,
+}
+
+#[derive(Default)]
+struct Sweep {
+    enabled: bool,
+    period: u8,
+    negate: bool,
+    shift: u8,
+    divider: u8,
+    reload: bool,
+}
+
+impl Pulse {
+    pub fn new(channel: PulseChannel) -> Self {
+        Self {
+            channel,
+            envelope: Envelope::default(),
+            length: LengthCounter::default(),
+            sweep: Sweep::default(),
+            duty: 0,
+            sequence_pos: 0,
+            timer_period: 0,
+            timer: 0,
+        }
+    }
+
+    pub fn write(&mut self, reg: u8, value: u8) {
+        match reg {
+            0 => {
+                self.duty = value >> 6;
+                self.length.halt = value & 0b0010_0000 != 0;
+                self.envelope.write(value);
+            }
+            1 => {
+                self.sweep.enabled = value & 0b1000_0000 != 0;
+                self.sweep.period  = (value >> 4) & 0b0111;
+                self.sweep.negate  = value & 0b0000_1000 != 0;
+                self.sweep.shift   = value & 0b0000_0111;
+                self.sweep.reload  = true;
+            }
+            2 => {
+                self.timer_period = (self.timer_period & 0x0700) | value as u16;
+            }
+            3 => {
+                self.timer_period = (self.timer_period & 0x00ff) | ((value as u16 & 0b0111) << 8);
+                self.length.load(value);
+                // Writing the high byte restarts the sequencer and the envelope
+                self.sequence_pos = 0;
+                self.envelope.start = true;
+            }
+            _ => unreachable!("invalid pulse register {reg}")
+        }
+    }
+
+    /// Clocked every APU cycle (every other CPU cycle)
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            self.sequence_pos = (self.sequence_pos + 1) % 8;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn clock_quarter_frame(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn clock_half_frame(&mut self) {
+        self.length.clock();
+
+        if self.sweep.divider == 0 && self.sweep.enabled && self.sweep.shift > 0 && !self.sweep_muted() {
+            self.timer_period = self.sweep_target();
+        }
+
+        if self.sweep.divider == 0 || self.sweep.reload {
+            self.sweep.divider = self.sweep.period;
+            self.sweep.reload = false;
+        } else {
+            self.sweep.divider -= 1;
+        }
+    }
+
+    fn sweep_target(&self) -> u16 {
+        let change = self.timer_period >> self.sweep.shift;
+        if self.sweep.negate {
+            match self.channel {
+                PulseChannel::One => self.timer_period.saturating_sub(change + 1),
+                PulseChannel::Two | PulseChannel::Expansion => self.timer_period.saturating_sub(change),
+            }
+        } else {
+            self.timer_period + change
+        }
+    }
+
+    /// The sweep unit mutes the channel even when it is disabled, expansion
+    /// pulses don't have one
+    fn sweep_muted(&self) -> bool {
+        self.channel != PulseChannel::Expansion && (self.timer_period < 8 || self.sweep_target() > 0x7ff)
+    }
+
+    pub fn output(&self) -> u8 {
+        if !self.length.active()
+            || self.sweep_muted()
+            || DUTY_TABLE[self.duty as usize][self.sequence_pos as usize] == 0
+        {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Pulse, PulseChannel};
+
+    #[test]
+    fn sweep_negate_differs_per_channel() {
+        let mut pulse1 = Pulse::new(PulseChannel::One);
+        let mut pulse2 = Pulse::new(PulseChannel::Two);
+
+        for pulse in [&mut pulse1, &mut pulse2] {
+            pulse.write(1, 0b1000_1001); // enabled, period 0, negate, shift 1
+            pulse.write(2, 0x00);
+            pulse.write(3, 0x01); // timer period 0x100
+        }
+
+        assert_eq!(pulse1.sweep_target(), 0x100 - 0x80 - 1);
+        assert_eq!(pulse2.sweep_target(), 0x100 - 0x80);
+    }
+
+    #[test]
+    fn sweep_mutes_low_periods() {
+        let mut pulse = Pulse::new(PulseChannel::One);
+        pulse.length.set_enabled(true);
+        pulse.write(0, 0b1101_1111); // duty 3, constant volume 15
+        pulse.write(2, 0x07);
+        pulse.write(3, 0b0000_1000);
+
+        assert!(pulse.sweep_muted());
+        assert_eq!(pulse.output(), 0);
+
+        pulse.write(2, 0x08);
+        assert!(!pulse.sweep_muted());
+        assert_eq!(pulse.output(), 15);
+    }
+
+    #[test]
+    fn expansion_pulses_never_mute() {
+        let mut pulse = Pulse::new(PulseChannel::Expansion);
+        pulse.length.set_enabled(true);
+        pulse.write(0, 0b1101_1111);
+        pulse.write(2, 0x07);
+        pulse.write(3, 0b0000_1000);
+        assert_eq!(pulse.output(), 15);
+
+        pulse.write(2, 0xff);
+        pulse.write(3, 0b0000_1111); // period 0x7ff, the APU sweep target would overflow
+        assert_eq!(pulse.output(), 15);
+    }
+}
@@ -0,0 +1,96 @@
+/*
+Records the APU output to WAV files, independently of any live audio output.
+The mix, including any cartridge expansion audio, is always recorded, and
+optionally a stem for each channel plus one for the expansion audio, written
+next to the mix as `<name>.<channel>.wav`.
+*/
+
+use std::path::{Path, PathBuf};
+
+use crate::audio::wav::WavWriter;
+
+use super::{mixer, resampler::Resampler};
+
+pub const STEM_NAMES: [&str; 5] = ["pulse1", "pulse2", "triangle", "noise", "dmc"];
+const EXPANSION_STEM: &str = "expansion";
+
+pub struct Recorder {
+    mix: Track,
+    stems: Vec<Track>,
+    expansion: Option<Track>,
+}
+
+struct Track {
+    resampler: Resampler,
+    writer: WavWriter,
+}
+
+impl Track {
+    fn create(path: &Path, sample_rate: u32) -> anyhow::Result<Self> {
+        Ok(Self {
+            resampler: Resampler::new(sample_rate),
+            writer: WavWriter::create(path, sample_rate)?,
+        })
+    }
+
+    fn push(&mut self, value: f32) -> anyhow::Result<()> {
+        if let Some(sample) = self.resampler.push(value) {
+            self.writer.write_sample(sample)?;
+        }
+        Ok(())
+    }
+}
+
+impl Recorder {
+    pub fn create(path: &Path, sample_rate: u32, stems: bool) -> anyhow::Result<Self> {
+        let channels = if stems {
+            STEM_NAMES.iter()
+                .map(|name| Track::create(&stem_path(path, name), sample_rate))
+                .collect::<anyhow::Result<Vec<_>>>()?
+        } else {
+            Vec::new()
+        };
+        let expansion = if stems {
+            Some(Track::create(&stem_path(path, EXPANSION_STEM), sample_rate)?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            mix: Track::create(path, sample_rate)?,
+            stems: channels,
+            expansion,
+        })
+    }
+
+    /// Pushes one CPU cycle of channel outputs, in the order of `STEM_NAMES`,
+    /// and the already mixed output of the cartridge's expansion audio
+    pub fn push(&mut self, outputs: [u8; 5], expansion: f32) -> anyhow::Result<()> {
+        let [pulse1, pulse2, triangle, noise, dmc] = outputs;
+        self.mix.push(mixer::mix(pulse1, pulse2, triangle, noise, dmc) + expansion)?;
+
+        for (i, stem) in self.stems.iter_mut().enumerate() {
+            let mut solo = [0; 5];
+            solo[i] = outputs[i];
+            let [pulse1, pulse2, triangle, noise, dmc] = solo;
+            stem.push(mixer::mix(pulse1, pulse2, triangle, noise, dmc))?;
+        }
+        if let Some(stem) = &mut self.expansion {
+            stem.push(expansion)?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> anyhow::Result<()> {
+        self.mix.writer.finish()?;
+        for stem in self.stems.into_iter().chain(self.expansion) {
+            stem.writer.finish()?;
+        }
+        Ok(())
+    }
+}
+
+fn stem_path(path: &Path, stem: &str) -> PathBuf {
+    let name = path.file_stem().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{name}.{stem}.wav"))
+}
@@ -0,0 +1,127 @@
+/*
+Downsamples the APU output from the CPU clock rate to the host sample rate,
+by averaging all the CPU cycles that fall within each output sample.
+
+The output is run through the same first-order filters as the NES audio path,
+a 90 Hz high-pass (removing the DC offset) and a 14 kHz low-pass.
+*/
+
+use std::f32::consts::PI;
+
+/// NTSC CPU clock rate in Hz
+pub const CPU_CLOCK_RATE: f64 = 1_789_773.0;
+
+/// How far the rate control is allowed to stretch the output rate, which
+/// `audio::RateControl` reaches at an empty or doubled buffer
+pub(crate) const MAX_RATE_ADJUST: f64 = 0.005;
+
+pub struct Resampler {
+    sample_rate: u32,
+    rate_adjust: f64,
+    phase: f64,
+    sum: f32,
+    count: u32,
+    high_pass: HighPass,
+    low_pass: LowPass,
+}
+
+impl Resampler {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            rate_adjust: 1.0,
+            phase: 0.0,
+            sum: 0.0,
+            count: 0,
+            high_pass: HighPass::new(90.0, sample_rate),
+            low_pass: LowPass::new(14000.0, sample_rate),
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Stretches the output rate by `ratio`, letting the host keep its buffer level steady
+    pub fn set_rate_adjust(&mut self, ratio: f64) {
+        self.rate_adjust = ratio.clamp(1.0 - MAX_RATE_ADJUST, 1.0 + MAX_RATE_ADJUST);
+    }
+
+    /// Pushes one CPU cycle worth of output, returning a host sample when one is complete
+    pub fn push(&mut self, value: f32) -> Option<f32> {
+        self.sum += value;
+        self.count += 1;
+
+        self.phase += self.sample_rate as f64 * self.rate_adjust;
+        if self.phase < CPU_CLOCK_RATE {
+            return None;
+        }
+        self.phase -= CPU_CLOCK_RATE;
+
+        let average = self.sum / self.count as f32;
+        self.sum = 0.0;
+        self.count = 0;
+
+        Some(self.low_pass.apply(self.high_pass.apply(average)))
+    }
+}
+
+struct HighPass {
+    alpha: f32,
+    prev_in: f32,
+    prev_out: f32,
+}
+
+impl HighPass {
+    fn new(cutoff: f32, sample_rate: u32) -> Self {
+        let rc = 1.0 / (2.0 * PI * cutoff);
+        let dt = 1.0 / sample_rate as f32;
+        Self { alpha: rc / (rc + dt), prev_in: 0.0, prev_out: 0.0 }
+    }
+
+    fn apply(&mut self, value: f32) -> f32 {
+        self.prev_out = self.alpha * (self.prev_out + value - self.prev_in);
+        self.prev_in = value;
+        self.prev_out
+    }
+}
+
+struct LowPass {
+    alpha: f32,
+    prev_out: f32,
+}
+
+impl LowPass {
+    fn new(cutoff: f32, sample_rate: u32) -> Self {
+        let rc = 1.0 / (2.0 * PI * cutoff);
+        let dt = 1.0 / sample_rate as f32;
+        Self { alpha: dt / (rc + dt), prev_out: 0.0 }
+    }
+
+    fn apply(&mut self, value: f32) -> f32 {
+        self.prev_out += self.alpha * (value - self.prev_out);
+        self.prev_out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Resampler, CPU_CLOCK_RATE};
+
+    fn samples_per_second(resampler: &mut Resampler) -> usize {
+        (0..CPU_CLOCK_RATE as usize).filter_map(|_| resampler.push(0.5)).count()
+    }
+
+    #[test]
+    fn resamples_to_host_rate() {
+        assert_eq!(samples_per_second(&mut Resampler::new(44100)), 44100);
+        assert_eq!(samples_per_second(&mut Resampler::new(48000)), 48000);
+    }
+
+    #[test]
+    fn rate_adjust_is_bounded() {
+        let mut resampler = Resampler::new(48000);
+        resampler.set_rate_adjust(0.5);
+        assert_eq!(samples_per_second(&mut resampler), 47760);
+    }
+}
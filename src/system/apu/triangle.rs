@@ -0,0 +1,81 @@
+/*
+$4008	CRRR RRRR	Length counter halt / linear counter control (C), linear counter load (R)
+$4009	---- ----	Unused
+$400A	TTTT TTTT	Timer low (T)
+$400B	LLLL LTTT	Length counter load (L), timer high (T)
+*/
+
+use super::length_counter::LengthCounter;
+
+const SEQUENCE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10,  9,  8,  7,  6,  5,  4,  3,  2,  1,  0,
+     0,  1,  2,  3,  4,  5,  6,  7,  8,  9, 10, 11, 12, 13, 14, 15,
+];
+
+#[derive(Default)]
+pub struct Triangle {
+    pub(crate) length: LengthCounter,
+    control: bool,
+    linear_reload_value: u8,
+    linear_counter: u8,
+    linear_reload: bool,
+    sequence_pos: u8,
+    timer_period: u16,
+    timer: u16,
+}
+
+impl Triangle {
+    pub fn write(&mut self, reg: u8, value: u8) {
+        match reg {
+            0 => {
+                self.control = value & 0b1000_0000 != 0;
+                self.length.halt = self.control;
+                self.linear_reload_value = value & 0b0111_1111;
+            }
+            1 => {}
+            2 => {
+                self.timer_period = (self.timer_period & 0x0700) | value as u16;
+            }
+            3 => {
+                self.timer_period = (self.timer_period & 0x00ff) | ((value as u16 & 0b0111) << 8);
+                self.length.load(value);
+                self.linear_reload = true;
+            }
+            _ => unreachable!("invalid triangle register {reg}")
+        }
+    }
+
+    /// Clocked every CPU cycle
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            // The sequencer is only stepped while both counters are non-zero
+            if self.linear_counter > 0 && self.length.active() {
+                self.sequence_pos = (self.sequence_pos + 1) % 32;
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn clock_quarter_frame(&mut self) {
+        if self.linear_reload {
+            self.linear_counter = self.linear_reload_value;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+
+        if !self.control {
+            self.linear_reload = false;
+        }
+    }
+
+    pub fn clock_half_frame(&mut self) {
+        self.length.clock();
+    }
+
+    /// A silenced triangle keeps outputting its current step, rather than dropping to zero
+    pub fn output(&self) -> u8 {
+        SEQUENCE[self.sequence_pos as usize]
+    }
+}
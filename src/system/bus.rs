@@ -15,14 +15,22 @@ use super::{ppu, addr::Addr, apu};
 
 impl super::System {
 
+    /// Reads from the CPU bus, taking one CPU cycle
     pub fn read_byte<A: Into<Addr>>(&mut self, addr: A) -> anyhow::Result<u8> {
-        let addr = addr.into();
+        self.tick_cycle()?;
+        self.bus_read(addr.into())
+    }
+
+    /// Reads from the CPU bus without advancing time, for DMA units that steal cycles from the CPU
+    pub(crate) fn bus_read(&mut self, addr: Addr) -> anyhow::Result<u8> {
         match self.map_addr(addr) {
             BusTarget::RAM(ra) => Ok(self.ram[ra]),
             BusTarget::PPU(ra) => ppu::read(self, ra as u8),
             BusTarget::APU(ra) => Ok(apu::read(self, ra as u8)),
+            BusTarget::Expansion => Ok(self.cart.mapper.expansion_read(addr).unwrap_or_else(|| open_bus(addr))),
             BusTarget::PRG => self.cart.mapper.cpu_read(addr),
             BusTarget::OAMDMA => anyhow::bail!("tried to read from OAM DMA"),
+            BusTarget::OpenBus => Ok(open_bus(addr)),
         }
     }
 
@@ -32,29 +40,40 @@ impl super::System {
             BusTarget::RAM(ra) => self.ram[ra],
             BusTarget::PPU(ra) => panic!("tried to peek into PPU {addr} ({ra:04x})"),
             BusTarget::APU(ra) => panic!("tried to peek into APU {addr} ({ra:04x})"),
+            BusTarget::Expansion => self.cart.mapper.expansion_peek(addr).unwrap_or_else(|| open_bus(addr)),
             BusTarget::PRG => self.cart.mapper.cpu_read(addr).unwrap(),
             BusTarget::OAMDMA => panic!("tried to read from OAM DMA"),
+            BusTarget::OpenBus => open_bus(addr),
         }
     }
 
+    /// Writes to the CPU bus, taking one CPU cycle
     pub fn write_byte<A: Into<Addr>>(&mut self, addr: A, value: u8) -> anyhow::Result<()> {
         let addr = addr.into();
-        match self.map_addr(addr.into()) {
+        self.tick_cycle()?;
+        self.cart.mapper.cpu_bus_write(addr, value);
+        match self.map_addr(addr) {
             BusTarget::RAM(ra) => self.ram[ra] = value,
             BusTarget::PPU(ra) => ppu::write(self, ra as u8, value)?,
             BusTarget::APU(ra) => apu::write(self, ra as u8, value),
+            BusTarget::Expansion => self.cart.mapper.expansion_write(addr, value),
             BusTarget::PRG => self.cart.write_byte(addr, value),
+            BusTarget::OpenBus => {}
             BusTarget::OAMDMA => {
                 // println!("Writing to OAM using DMA on bank {value:02x}");
-                assert_eq!(self.ppu.oam_addr, 0);
-                for lsb in 0..255 {
-                    self.oam[lsb] = self.read_byte(Addr::from_bytes(value, lsb as u8))?;
+                // The CPU halts for a cycle, plus one more to line up with a read cycle, then 256 alternating read/write cycles
+                self.tick_cycle()?;
+                if self.cycles % 2 == 1 {
+                    self.tick_cycle()?;
+                }
+                for lsb in 0..=255 {
+                    let byte = self.read_byte(Addr::from_bytes(value, lsb))?;
+                    self.tick_cycle()?;
+                    // the copy starts at OAMADDR and wraps around
+                    self.oam[self.ppu.oam_addr.wrapping_add(lsb) as usize] = byte;
                 }
 
                 // self.dump_oam();
-
-                //513 or 514 cycles after the $4014 write tick. (1 wait state cycle while waiting for writes to complete, +1 if on an odd CPU cycle, then 256 alternating read/write cycles.)
-                self.cycles += 513;
             }
         }
         Ok(())
@@ -64,14 +83,14 @@ impl super::System {
 
     pub fn read_word<A: Into<Addr>>(&mut self, addr: A) -> anyhow::Result<u16> {
         let addr = addr.into();
-        let high = self.read_byte(addr + 1)?;
         let low = self.read_byte(addr)?;
+        let high = self.read_byte(addr + 1)?;
         Ok(((high as u16) << 8) | low as u16)
     }
 
     pub fn read_zero_word(&mut self, addr: u8) -> anyhow::Result<u16> {
-        let high = self.read_byte(Addr::from_zero(addr.wrapping_add(1)))?;
         let low = self.read_byte(Addr::from_zero(addr))?;
+        let high = self.read_byte(Addr::from_zero(addr.wrapping_add(1)))?;
         Ok(((high as u16) << 8) | low as u16)
     }
 
@@ -102,7 +121,7 @@ impl super::System {
         } else if addr.0 <= 0x1fff {
             // $1000-$17FF	$0800
             // $1800-$1FFF	$0800
-            panic!("invalid address")
+            BusTarget::RAM((addr.0 % 0x800) as usize)
         } else if addr.0 <= 0x2007 {
             // $2000-$2007	$0008	NES PPU registers
             BusTarget::PPU( (addr.0 - 0x2000) as usize )
@@ -116,18 +135,30 @@ impl super::System {
             BusTarget::APU((addr.0 - 0x4000) as usize )
         } else if addr.0 <= 0x401f {
             // $4018-$401F	$0008	APU and I/O functionality that is normally disabled. See CPU Test Mode.
-            panic!("invalid address")
+            BusTarget::OpenBus
+        } else if addr.0 <= 0x5fff {
+            // $4020-$5FFF	Cartridge expansion area, only some mappers put registers here
+            BusTarget::Expansion
         } else {
-            // $4020-$FFFF	$BFE0	Cartridge space: PRG ROM, PRG RAM, and mapper registers
+            // $6000-$FFFF	Cartridge space: PRG ROM, PRG RAM, and mapper registers
             BusTarget::PRG
         }
     }
 }
 
+/// Nothing drives the data bus, so it still holds the high byte of the
+/// address from the last operand fetch
+pub(crate) fn open_bus(addr: Addr) -> u8 {
+    (addr.0 >> 8) as u8
+}
+
 pub enum BusTarget {
     RAM(usize),
     PPU(usize),
     APU(usize),
+    /// $4020-$5FFF, routed to the mapper's expansion registers
+    Expansion,
     PRG,
     OAMDMA,
+    OpenBus,
 }
@@ -68,14 +68,6 @@ impl Cart {
         self.mapper.cpu_write(addr, value).unwrap();
     }
 
-    pub(crate) fn get_tile(&self, addr: u16) -> anyhow::Result<(u8, u8)> {
-        let upper_addr = Addr(addr);
-        let lower_addr = upper_addr + 8;
-        let upper_sliver = self.mapper.ppu_read(upper_addr)?;// .chr_rom[upper_addr as usize];
-        let lower_sliver = self.mapper.ppu_read(lower_addr)?;// as usize];
-        Ok((upper_sliver, lower_sliver))
-    }
-
     pub(crate) fn is_empty(&self) -> bool {
         self.is_empty
     }
@@ -90,7 +82,9 @@ pub struct Header {
     pub(crate) no_mirror: bool,
     pub(crate) prg_rom_size: usize,
     pub(crate) chr_rom_size: usize,
-    pub(crate) mapper_id: u16
+    pub(crate) mapper_id: u16,
+    /// Whether the PRG ROM fights with the CPU over the data bus on writes
+    pub(crate) bus_conflicts: bool,
 }
 
 enum HeaderType {
@@ -220,7 +214,7 @@ impl Header {
 
 
             #[allow(unused_variables)]
-            {
+            let bus_conflicts = {
                 let flags9 = bytes.next().ok_or_else(too_short_err)??;
                 log_flags(log, "Byte 9 Flags",  flags9, "T???????", &col_true, &col_false);
                 log_var(log, " => TV System", if flags9&1!=0 {"PAL"}else{"NTSC"}, &col_enum);
@@ -232,8 +226,10 @@ impl Header {
                     _ => "Dual",
                 }, &col_enum);
                 log_bool(log, " => PRG RAM", flags10&0b0001_0000!=0, &col_true, &col_false);
-                log_bool(log, " => Bus conflicts", flags10&0b0010_0000!=0, &col_true, &col_false);
-            }
+                let bus_conflicts = flags10&0b0010_0000!=0;
+                log_bool(log, " => Bus conflicts", bus_conflicts, &col_true, &col_false);
+                bus_conflicts
+            };
 
             let mapper_id = (flags6 as u16 & 0xf0) >> 4 | (flags7 as u16 & 0xf0);
 
@@ -260,7 +256,8 @@ impl Header {
                 trainer,
                 no_mirror,
                 vertical_mirroring,
-                battery_ram
+                battery_ram,
+                bus_conflicts,
             })
 
             
@@ -274,3 +271,21 @@ impl Header {
 
 
 
+
+#[cfg(test)]
+impl Header {
+    /// A plain iNES header, for building mappers in tests
+    pub(crate) fn for_tests(mapper_id: u16, prg_rom_size: usize, chr_rom_size: usize) -> Self {
+        Self {
+            header_type: HeaderType::INES(INESHeader {}),
+            vertical_mirroring: false,
+            battery_ram: false,
+            trainer: false,
+            no_mirror: false,
+            prg_rom_size,
+            chr_rom_size,
+            mapper_id,
+            bus_conflicts: false,
+        }
+    }
+}
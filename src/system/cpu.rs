@@ -92,13 +92,9 @@ impl CPU {
     }
 
     fn stack_pull_word(sys: &mut System) -> anyhow::Result<u16> {
-        sys.cpu.sp = sys.cpu.sp.checked_add(1).expect("stack overflow!");
-        let addr = CPU::addr_stack(sys.cpu.sp);
-        let value = sys.read_word(addr)?;
-        sys.cpu.sp = sys.cpu.sp.checked_add(1).expect("stack overflow!");
-
-        // println!("{value:04x} read from stack at {addr:04x}");
-        Ok(value)
+        let lsb = Self::stack_pull_byte(sys)?;
+        let msb = Self::stack_pull_byte(sys)?;
+        Ok(Addr::from_bytes(msb, lsb).into())
     }
 
     
@@ -112,7 +108,37 @@ impl CPU {
 
     pub const STACK_BOT: Addr = Addr(0x0100);
     const STACK_TOP: Addr = Addr(0x01ff);
-    const ADDR_BREAK: Addr = Addr(0xfffe);
+    pub(crate) const ADDR_NMI: Addr = Addr(0xfffa);
+    /// Shared by IRQ and BRK
+    pub(crate) const ADDR_IRQ: Addr = Addr(0xfffe);
+
+    /// Runs the 7 cycle interrupt sequence: pushes the return address and status, then jumps through `vector`.
+    /// The B flag is only set in the pushed status for BRK, which is how handlers tell it apart from an IRQ.
+    pub(crate) fn interrupt(sys: &mut System, vector: Addr, brk: bool) -> anyhow::Result<()> {
+        if brk {
+            // BRK is followed by a padding byte, which is skipped on return
+            shift_pc(sys)?;
+        } else {
+            // Hardware interrupts replace the opcode fetch by two dummy reads
+            dummy_read_pc(sys)?;
+            dummy_read_pc(sys)?;
+        }
+        CPU::stack_push_word(sys, sys.cpu.pc.into())?;
+
+        // An NMI raised by now hijacks a BRK or IRQ, which then jumps through the NMI vector, keeping the pushed B flag
+        let vector = if vector == CPU::ADDR_IRQ && std::mem::take(&mut sys.nmi) {CPU::ADDR_NMI} else {vector};
+
+        let status = sys.cpu.status() & !(1 << 4);
+        CPU::stack_push_byte(sys, if brk {status | (1 << 4)} else {status})?;
+
+        sys.cpu.interrupt = true;
+        sys.cpu.pc = sys.read_addr(vector)?;
+
+        // The sequence doesn't poll, the first instruction of the handler always runs
+        sys.pending_nmi = false;
+        sys.pending_irq = false;
+        Ok(())
+    }
 
     pub(crate) fn addr_stack(sp: u8) -> Addr {
         CPU::STACK_BOT + (sp as u16)
@@ -162,7 +188,7 @@ impl CPU {
 
 #[cfg(test)]
 mod tests {
-    use crate::system::cpu::CPU;
+    use crate::system::{System, addr::Addr, cpu::CPU, options::Options};
 
     #[test]
     fn cpu_flags_roundtrip() {
@@ -183,80 +209,116 @@ mod tests {
         test_roundtrip(0b0010_1111);
         test_roundtrip(0b1010_1010);
     }
+
+    fn run_op(code: &[u8], setup: impl FnOnce(&mut System)) -> System {
+        let mut sys = System::new(Options::default()).unwrap();
+        sys.ram[0x0300..0x0300 + code.len()].copy_from_slice(code);
+        sys.cpu.pc = Addr(0x0300);
+        setup(&mut sys);
+        let (op, am) = super::load(&mut sys).unwrap();
+        op.execute(&mut sys, &am).unwrap();
+        sys
+    }
+
+    #[test]
+    fn unofficial_immediate_opcodes() {
+        // ARR: C from bit 6, V from bit 6 XOR bit 5
+        let sys = run_op(&[0x6b, 0xff], |sys| { sys.cpu.a = 0xc0; sys.cpu.carry = true; });
+        assert_eq!(sys.cpu.a, 0xe0);
+        assert!(sys.cpu.carry && !sys.cpu.overflow);
+
+        // SBX: (A AND X) - M -> X, ignoring the carry
+        let sys = run_op(&[0xcb, 0x01], |sys| { sys.cpu.a = 0x0f; sys.cpu.x = 0x3c; });
+        assert_eq!(sys.cpu.x, 0x0b);
+        assert!(sys.cpu.carry);
+
+        // LXA with the default magic constant
+        let sys = run_op(&[0xab, 0x5a], |sys| sys.cpu.a = 0x00);
+        assert_eq!((sys.cpu.a, sys.cpu.x), (0x4a, 0x4a));
+    }
+
+    #[test]
+    fn unofficial_store_high_opcodes() {
+        // SHX $02f0,Y without page crossing stores X AND (H+1)
+        let sys = run_op(&[0x9e, 0xf0, 0x02], |sys| { sys.cpu.x = 0xff; sys.cpu.y = 0x01; });
+        assert_eq!(sys.ram[0x02f1], 0x03);
+
+        // Crossing a page replaces the high byte of the address by the value
+        let sys = run_op(&[0x9e, 0xf0, 0x02], |sys| { sys.cpu.x = 0x05; sys.cpu.y = 0x20; });
+        assert_eq!(sys.ram[0x0110], 0x01);
+    }
+
+    #[test]
+    fn jam_halts_on_the_opcode() {
+        let sys = run_op(&[0x02], |_| {});
+        assert_eq!(sys.cpu.pc.0, 0x0300);
+    }
+}
+
+/// Resolves the operand address of a read instruction, indexed modes only take the extra cycle when crossing a page
+pub(crate) fn resolve_addr(sys: &mut System, address_mode: &AddressMode) -> anyhow::Result<Addr> {
+    get_addr(sys, address_mode, false)
+}
+
+/// Resolves the operand address of a write or read-modify-write instruction, indexed modes always take the extra cycle
+pub(crate) fn resolve_addr_for_write(sys: &mut System, address_mode: &AddressMode) -> anyhow::Result<Addr> {
+    get_addr(sys, address_mode, true)
 }
 
-pub(crate) fn get_addr_ro(sys: &mut System, address_mode: &AddressMode) -> anyhow::Result<AddrLookup> {
+fn get_addr(sys: &mut System, address_mode: &AddressMode, write: bool) -> anyhow::Result<Addr> {
     match address_mode {
         AddressMode::Zero(reg) => addr_zero(sys, reg),
-        AddressMode::Absolute(reg) => addr_absolute(sys, reg),
+        AddressMode::Absolute(reg) => addr_absolute(sys, reg, write),
         AddressMode::Immediate => Ok(addr_immediate(sys)),
-        AddressMode::Indirect(reg) => addr_indirect(sys, reg),
+        AddressMode::Indirect(reg) => addr_indirect(sys, reg, write),
         address_mode => panic!("getting address mode {address_mode:?} is not implemented")
     } 
 }
 
-pub(crate) fn resolve_addr(sys: &mut System, address_mode: &AddressMode) -> anyhow::Result<Addr> {
-    let AddrLookup {addr, crossed_page} = get_addr_ro(sys, address_mode)?;
-    sys.cycles += default_cycles(address_mode, crossed_page);
-    Ok(addr)
-}
-
-pub(crate) fn resolve_addr_with_xp(sys: &mut System, address_mode: &AddressMode, xp: bool) -> anyhow::Result<Addr> {
-    let addr = get_addr_ro(sys, address_mode)?.addr;
-    sys.cycles += default_cycles(address_mode, xp);
-    Ok(addr)
+/// The CPU adds the index to the low byte first, and reads from that address while it fixes up the high byte
+fn dummy_read_unfixed(sys: &mut System, base: Addr, addr: Addr, write: bool) -> anyhow::Result<()> {
+    if write || !addr.same_page_as(base) {
+        sys.read_byte(Addr::from_bytes(base.msb(), addr.lsb()))?;
+    }
+    Ok(())
 }
 
-pub(crate) fn default_cycles(address_mode: &AddressMode, xp: bool) -> u64 {
-    match address_mode { 
-        AddressMode::Register(_) => 0,
-        AddressMode::Immediate => 2,
-        AddressMode::Zero(None) => 3,
-        AddressMode::Absolute(Some(_)) if xp => 5,
-        AddressMode::Absolute(Some(_)) => 4,
-        AddressMode::Indirect(Some(Register::X)) => 6,
-        AddressMode::Indirect(Some(Register::Y)) if xp => 6,
-        AddressMode::Indirect(Some(Register::Y)) => 5,
-         _ => 4
-    }
+/// Reads the byte at PC and throws it away, which is what the CPU does on cycles it spends internally
+pub(crate) fn dummy_read_pc(sys: &mut System) -> anyhow::Result<()> {
+    sys.read_byte(sys.cpu.pc)?;
+    Ok(())
 }
 
-pub(crate) fn addr_immediate(sys: &mut System) -> AddrLookup {
+pub(crate) fn addr_immediate(sys: &mut System) -> Addr {
     let addr = sys.cpu.pc;
     sys.cpu.pc += 1i8;
-    AddrLookup { addr, crossed_page: false }
-
+    addr
 }
 
-pub(crate) fn addr_zero(sys: &mut System, reg: &Option<Register>) -> anyhow::Result<AddrLookup> {
-    let offset = match reg {
-        None => 0,
-        Some(r) => sys.cpu.get_reg(r)
-    };
-
+pub(crate) fn addr_zero(sys: &mut System, reg: &Option<Register>) -> anyhow::Result<Addr> {
     // Zero-extended address
     let base = shift_pc(sys)?;
 
-    let sum = base.wrapping_add(offset);
-    // eprintln!("Base: {base:04x} offset: {offset:02x} sum:{sum:02x}");
-    let addr = Addr::from_zero(sum);
-
-    // TODO
-    let crossed_page = false;
+    let offset = match reg {
+        None => return Ok(Addr::from_zero(base)),
+        Some(r) => sys.cpu.get_reg(r)
+    };
 
-    Ok(AddrLookup { addr, crossed_page })
-    
-    // sys.cpu.index_reg(base, reg)
+    // The base address is read while the index is added, which wraps around within the zero page
+    sys.read_byte(Addr::from_zero(base))?;
+    Ok(Addr::from_zero(base.wrapping_add(offset)))
 }
 
-pub(crate) fn addr_absolute(sys: &mut System, reg: &Option<Register>) -> anyhow::Result<AddrLookup> {
+pub(crate) fn addr_absolute(sys: &mut System, reg: &Option<Register>, write: bool) -> anyhow::Result<Addr> {
     let base = shift_pc_addr(sys)?;
     let addr = sys.cpu.index_reg(base, reg);
-
-    Ok(AddrLookup { addr, crossed_page: !addr.same_page_as(base) })
+    if reg.is_some() {
+        dummy_read_unfixed(sys, base, addr, write)?;
+    }
+    Ok(addr)
 }
 
-pub(crate) fn addr_indirect(sys: &mut System, reg: &Option<Register>) -> anyhow::Result<AddrLookup> {
+pub(crate) fn addr_indirect(sys: &mut System, reg: &Option<Register>, write: bool) -> anyhow::Result<Addr> {
     match reg {
         None => {
             let lsb_addr = shift_pc_addr(sys)?;
@@ -268,29 +330,21 @@ pub(crate) fn addr_indirect(sys: &mut System, reg: &Option<Register>) -> anyhow:
             } else {
                 lsb_addr + 1
             })?;
-            let addr = Addr::from_bytes(addr_msb, addr_lsb);
-            // let next_c = sys.read_byte(addr - 0xff);
-            // eprintln!("addr1: {addr:04x} a: {next_a:02x} b: {next_b:02x} next: {next:04x}");
-            Ok(AddrLookup { addr, crossed_page: !addr.same_page_as(lsb_addr) })
+            Ok(Addr::from_bytes(addr_msb, addr_lsb))
         },
         Some(Register::X) => {
             let lsb_addr = shift_pc(sys)?;
-            let msb_addr = sys.cpu.x;
-            let meta_addr = lsb_addr.wrapping_add(msb_addr);
-            let addr = sys.read_zero_word(meta_addr)?.into();
-            Ok(AddrLookup{addr, crossed_page: false})
+            // The pointer is read while X is added to it
+            sys.read_byte(Addr::from_zero(lsb_addr))?;
+            let meta_addr = lsb_addr.wrapping_add(sys.cpu.x);
+            Ok(sys.read_zero_word(meta_addr)?.into())
         }
         Some(Register::Y) => {
             let lsb_addr = shift_pc(sys)?;
-            let addr_lsb = sys.read_zero_addr(lsb_addr)?; // FFFF
-            
-            let msb_addr = sys.cpu.y;
-            
-            let addr = addr_lsb + (msb_addr as u16);
-            // eprintln!("addr1: {addr_lsb:04x} lsb: {lsb_addr:02x} msb: {msb_addr:02x} addr: {addr:04x}");
-            let crossed_page = !addr.same_page_as(addr_lsb);
-  
-            Ok(AddrLookup{addr, crossed_page})
+            let base = sys.read_zero_addr(lsb_addr)?;
+            let addr = base + (sys.cpu.y as u16);
+            dummy_read_unfixed(sys, base, addr, write)?;
+            Ok(addr)
         }
         Some(r) => anyhow::bail!("cannot read indirect from register {r:?}")
     }
@@ -312,11 +366,6 @@ pub fn shift_pc_addr(sys: &mut System) -> anyhow::Result<Addr> {
     Ok(Addr::from_bytes(msb, lsb))
 }
 
-pub(crate) struct AddrLookup {
-    addr: Addr,
-    crossed_page: bool
-}
-
 #[derive(Debug)]
 pub(crate) enum Flag {
     Carry,
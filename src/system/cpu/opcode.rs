@@ -1,8 +1,6 @@
 use crate::{system::{System, addr::Addr}};
 
-use crate::system::cpu::{AddressMode, Register, CPU, addr_relative, Flag, resolve_addr, get_addr_ro};
-
-use super::{resolve_addr_with_xp, default_cycles};
+use crate::system::cpu::{AddressMode, Register, CPU, addr_relative, Flag, resolve_addr, resolve_addr_for_write, shift_pc, dummy_read_pc};
 
 
 
@@ -55,40 +53,60 @@ pub(crate) enum OpCode {
     RotLeftAndHack,
     ShiftRightOrHack,
     RotRightAddHack,
+    StoreHighHack(Register, Register),
+    StackStoreHighHack,
+    LoadStackHack,
+    AndCarryHack,
+    AndShiftRightHack,
+    AndRotRightHack,
+    AndSubXHack,
+    MagicAndHack,
+    MagicLoadHack,
+    Jam,
 }
 
 impl OpCode {
+    /// Executes the instruction, every bus access takes a CPU cycle and ticks the other devices along.
+    /// Returns the number of CPU cycles taken, not counting the opcode fetch.
     pub fn execute(&self, sys: &mut System, address_mode: &AddressMode) -> anyhow::Result<u64> {
 
         let cycles_start = sys.cycles;
         // sys.cpu.pc += 1i8;
 
-        let cycles = match self {
+        match self {
             OpCode::Jump => {
-                let addr = get_addr_ro(sys, address_mode)?.addr;
+                let addr = resolve_addr(sys, address_mode)?;
                 sys.cpu.pc = addr; 
-                match address_mode { AddressMode::Indirect(_) => 5, _ => 3 }
             }
 
             OpCode::JumpSub => {
                 assert!(matches!(address_mode, AddressMode::Absolute(None)));
-                let addr = resolve_addr(sys, address_mode)?;
-                let pc = sys.cpu.pc - 1;
-                CPU::stack_push_word(sys, pc.into())?;
-                sys.cpu.pc = addr; 
-                2
+                let lsb = shift_pc(sys)?;
+                // Internal cycle, while the low byte is stored away
+                sys.read_byte(CPU::addr_stack(sys.cpu.sp))?;
+                // The pushed address is the one of the high byte, which is only fetched afterwards
+                CPU::stack_push_word(sys, sys.cpu.pc.into())?;
+                let msb = sys.read_byte(sys.cpu.pc)?;
+                sys.cpu.pc = Addr::from_bytes(msb, lsb);
             }
 
             OpCode::BranchIf(flag, value) => {
                 assert!(matches!(address_mode, AddressMode::Relative));
                 let addr = addr_relative(sys)?;
                 if sys.cpu.get_flag(flag) == *value {
+                    // A taken branch doesn't poll interrupts on its extra cycle, unless it crosses a page
+                    let polled = (sys.pending_nmi, sys.pending_irq);
+                    dummy_read_pc(sys)?;
 
                     let old_pc = sys.cpu.pc;
                     sys.cpu.pc += addr;
-                    // If the new PC is on another page, add +2 cycles
-                    if sys.cpu.pc.same_page_as(old_pc) {3} else {4}
-                } else {2}
+                    if sys.cpu.pc.same_page_as(old_pc) {
+                        (sys.pending_nmi, sys.pending_irq) = polled;
+                    } else {
+                        // The high byte of the PC is fixed up on another cycle
+                        sys.read_byte(Addr::from_bytes(old_pc.msb(), sys.cpu.pc.lsb()))?;
+                    }
+                }
             }
 
             OpCode::Load(target_reg) => {
@@ -96,30 +114,28 @@ impl OpCode {
                 let value = sys.read_byte(addr)?;
                 sys.cpu.set_reg(target_reg, value);
                 sys.cpu.update_flags(value);
-                0
             }
 
             OpCode::Store(reg) => {
-                // let addr = resolve_addr(sys, address_mode)?;
-                let addr = resolve_addr_with_xp(sys, address_mode, true)?;
+                let addr = resolve_addr_for_write(sys, address_mode)?;
                 let value = sys.cpu.get_reg(reg);
                 sys.write_byte(addr, value)?;
-                0
             }
 
             OpCode::NoOp => {
                 match address_mode {
-                    AddressMode::Implied => {2},
+                    AddressMode::Implied => dummy_read_pc(sys)?,
                     _ => {
-                        // Dummy functions still need to push the PC
-                        let xp = get_addr_ro(sys, address_mode)?.crossed_page;
-                        default_cycles(address_mode, xp)
+                        // The unofficial NOPs still read their operand
+                        let addr = resolve_addr(sys, address_mode)?;
+                        sys.read_byte(addr)?;
                     }
                 }
-                
             }
 
             OpCode::SetFlag(flag, value) => {
+                // The flag changes after the interrupts have been polled, so CLI and SEI only affect IRQs after the next instruction
+                dummy_read_pc(sys)?;
                 match flag {
                     Flag::Carry => sys.cpu.carry = *value,
                     Flag::Decimal => sys.cpu.decimal = *value,
@@ -127,7 +143,6 @@ impl OpCode {
                     Flag::Overflow => sys.cpu.overflow = *value,
                     flag => panic!("setting flag {flag:?} is not implemented")
                 };
-                2
             }
 
             OpCode::Bit => {
@@ -136,61 +151,64 @@ impl OpCode {
                 sys.cpu.zero = (sys.cpu.a & value) == 0;
                 sys.cpu.overflow = (value & 0b01000000) != 0;
                 sys.cpu.sign = (value & 0b10000000) != 0;
-                0
             }
 
             OpCode::ReturnSub => {
+                dummy_read_pc(sys)?;
+                sys.read_byte(CPU::addr_stack(sys.cpu.sp))?;
                 let pc = Addr(CPU::stack_pull_word(sys)?);
-                sys.cpu.pc = pc + 1;
-                6
+                sys.cpu.pc = pc;
+                // The pulled address points at the last byte of the JSR, skip it
+                shift_pc(sys)?;
             }
 
             OpCode::ReturnInt => {
-                
+                dummy_read_pc(sys)?;
+                sys.read_byte(CPU::addr_stack(sys.cpu.sp))?;
                 let flags = CPU::stack_pull_byte(sys)?;
                 sys.cpu.set_status(flags);
                 let pc = CPU::stack_pull_word(sys)?.into();
                 sys.cpu.pc = pc;
-                6
             }
 
             OpCode::PushFlags => {
+                dummy_read_pc(sys)?;
                 let flags = sys.cpu.status();
                 // Always push the status with B bit set to the stack
                 CPU::stack_push_byte(sys, flags | (1u8 << 4))?;
-                3
             }
 
             
             OpCode::PullFlags => {
+                dummy_read_pc(sys)?;
+                sys.read_byte(CPU::addr_stack(sys.cpu.sp))?;
+                // Pulled on the last cycle, after the interrupts have been polled, like CLI and SEI
                 let flags = CPU::stack_pull_byte(sys)?;
                 sys.cpu.set_status(flags);
-                4
             }
 
 
             OpCode::PullAcc => {
-                
+                dummy_read_pc(sys)?;
+                sys.read_byte(CPU::addr_stack(sys.cpu.sp))?;
                 let value = CPU::stack_pull_byte(sys)?;
                 sys.cpu.set_reg(&Register::A, value);
                 sys.cpu.update_flags(value);
-                4
             }
 
             OpCode::PushAcc => {
+                dummy_read_pc(sys)?;
                 CPU::stack_push_byte(sys, sys.cpu.a)?;
-                3
             }
             
             OpCode::And => {
-                let addr = resolve_addr(sys, &address_mode)?;
+                let addr = resolve_addr(sys, address_mode)?;
                 sys.cpu.a &=  sys.read_byte(addr)?;
                 sys.cpu.update_flags(sys.cpu.a);
-                0
             }
 
             OpCode::Add => {
-                let addr = resolve_addr(sys, &address_mode)?;
+                let addr = resolve_addr(sys, address_mode)?;
                 //let (value, carry) = sys.cpu.a.carrying_add(sys.read_byte(addr), sys.cpu.carry);
                 let ack_val = sys.cpu.a;// & 0b10000000;
                 let rhs_val = sys.read_byte(addr)?;
@@ -211,39 +229,33 @@ impl OpCode {
                 sys.cpu.carry = overflow;
 
                 sys.cpu.overflow = if overflow {false} else {org_sign != sys.cpu.sign};
-
-                0
             }
             
             OpCode::Sub => {
-                let addr = resolve_addr(sys, &address_mode)?;
+                let addr = resolve_addr(sys, address_mode)?;
                 let sub = sys.read_byte(addr)?;
                 let ack = sys.cpu.a;
 
                 cpu_sub(sys, sub, ack, sys.cpu.carry);
-                
-                0
             }
                         
             OpCode::Or => {
-                let addr = resolve_addr(sys, &address_mode)?;
+                let addr = resolve_addr(sys, address_mode)?;
                 sys.cpu.a |=  sys.read_byte(addr)?;
                 sys.cpu.zero = sys.cpu.a == 0;
                 sys.cpu.sign = (sys.cpu.a & 0b10000000) != 0;
-                0
             }
 
                         
             OpCode::ExOr => {
-                let addr = resolve_addr(sys, &address_mode)?;
+                let addr = resolve_addr(sys, address_mode)?;
                 sys.cpu.a ^=  sys.read_byte(addr)?;
                 sys.cpu.zero = sys.cpu.a == 0;
                 sys.cpu.sign = (sys.cpu.a & 0b10000000) != 0;
-                0
             }
 
             OpCode::Compare(reg) => {
-                let addr = resolve_addr(sys, &address_mode)?;
+                let addr = resolve_addr(sys, address_mode)?;
                 let value_m = sys.read_byte(addr)?;
                 let value_r = sys.cpu.get_reg(reg);
                 sys.cpu.carry = value_r >= value_m;
@@ -251,134 +263,80 @@ impl OpCode {
                 sys.cpu.update_flags(val);
                 sys.cpu.zero = value_r == value_m;
                 //sys.cpu.soft_break = true;
-                
-                0
             }
 
             OpCode::Inc(Some(reg)) => {
+                dummy_read_pc(sys)?;
                 let (value, _) = sys.cpu.get_reg(reg).overflowing_add(1);
                 sys.cpu.set_reg(reg, value);
                 sys.cpu.update_flags(value);
-                2
             }
 
             OpCode::Inc(None) => {
-                let addr = resolve_addr_with_xp(sys, &address_mode, true)?;
-                let (value, _) = sys.read_byte(addr)?.overflowing_add(1);
-                sys.write_byte(addr, value)?;
+                let value = read_modify_write(sys, address_mode, |_, v| v.wrapping_add(1))?;
                 sys.cpu.update_flags(value);
-                2
             }
 
             OpCode::Dec(Some(reg)) => {
+                dummy_read_pc(sys)?;
                 let (value, _) = sys.cpu.get_reg(reg).overflowing_sub(1);
                 sys.cpu.set_reg(reg, value);
                 sys.cpu.update_flags(value);
-                2
             }
 
             OpCode::Dec(None) => {
-                let addr = resolve_addr_with_xp(sys, &address_mode, true)?;
-                let (value, _) = sys.read_byte(addr)?.overflowing_sub(1);
-                sys.write_byte(addr, value)?;
+                let value = read_modify_write(sys, address_mode, |_, v| v.wrapping_sub(1))?;
                 sys.cpu.update_flags(value);
-                2
             }
 
             OpCode::Transfer(src, dst) => {
+                dummy_read_pc(sys)?;
                 let value = sys.cpu.get_reg(src);
                 sys.cpu.set_reg(dst, value);
                 match dst {
                     Register::SP => {} // No flags updated
                     _ => sys.cpu.update_flags(value)
                 };
-                2
             }
 
             OpCode::ShiftRight => {
-                let (value, carry) = match address_mode {
-                    AddressMode::Register(r) => {
-                        let (value, carry) = shift_right(sys.cpu.get_reg(r));
-                        sys.cpu.set_reg(r, value);
-                        (value, carry)
-                    },
-                    _ => {
-                        let addr = resolve_addr_with_xp(sys, &address_mode, true)?;
-                        let (value, carry) = shift_right(sys.read_byte(addr)?);
-                        sys.write_byte(addr, value)?;
-                        (value, carry)
-                    }
-                };
-                sys.cpu.carry = carry;
+                let value = shift_op(sys, address_mode, |cpu, v| {
+                    let (value, carry) = shift_right(v);
+                    cpu.carry = carry;
+                    value
+                })?;
                 sys.cpu.update_flags(value);
-                2
             }
 
             OpCode::ShiftLeft => {
-                let (value, carry) = match address_mode {
-                    AddressMode::Register(r) => {
-                        let (value, carry) = shift_left(sys.cpu.get_reg(r));
-                        sys.cpu.set_reg(r, value);
-                        (value, carry)
-                    },
-                    _ => {
-                        let addr = resolve_addr_with_xp(sys, &address_mode, true)?;
-                        let (value, carry) = shift_left(sys.read_byte(addr)?);
-                        sys.write_byte(addr, value)?;
-                        (value, carry)
-                    }
-                };
-                sys.cpu.carry = carry;
+                let value = shift_op(sys, address_mode, |cpu, v| {
+                    let (value, carry) = shift_left(v);
+                    cpu.carry = carry;
+                    value
+                })?;
                 sys.cpu.update_flags(value);
-                2
             }
 
             OpCode::RotateRight => {
-                let (value, carry) = match address_mode {
-                    AddressMode::Register(r) => {
-                        let (value, carry) = rot_right(sys.cpu.get_reg(r), sys.cpu.carry);
-                        sys.cpu.set_reg(r, value);
-                        (value, carry)
-                    },
-                    _ => {
-                        let addr = resolve_addr_with_xp(sys, &address_mode, true)?;
-                        let (value, carry) = rot_right(sys.read_byte(addr)?, sys.cpu.carry);
-                        sys.write_byte(addr, value)?;
-                        (value, carry)
-                    }
-                };
-                sys.cpu.carry = carry;
+                let value = shift_op(sys, address_mode, |cpu, v| {
+                    let (value, carry) = rot_right(v, cpu.carry);
+                    cpu.carry = carry;
+                    value
+                })?;
                 sys.cpu.update_flags(value);
-                2
             }
 
             OpCode::RotateLeft => {
-                let (value, carry) = match address_mode {
-                    AddressMode::Register(r) => {
-                        let (value, carry) = rot_left(sys.cpu.get_reg(r), sys.cpu.carry);
-                        sys.cpu.set_reg(r, value);
-                        (value, carry)
-                    },
-                    _ => {
-                        let addr = resolve_addr_with_xp(sys, &address_mode, true)?;
-                        let (value, carry) = rot_left(sys.read_byte(addr)?, sys.cpu.carry);
-                        sys.write_byte(addr, value)?;
-                        (value, carry)
-                    }
-                };
-                sys.cpu.carry = carry;
+                let value = shift_op(sys, address_mode, |cpu, v| {
+                    let (value, carry) = rot_left(v, cpu.carry);
+                    cpu.carry = carry;
+                    value
+                })?;
                 sys.cpu.update_flags(value);
-                2
             }
 
             OpCode::Break => {
-                CPU::stack_push_word(sys, sys.cpu.pc.into())?;
-                CPU::stack_push_byte(sys, sys.cpu.status())?;
-                
-                sys.cpu.pc = CPU::ADDR_BREAK;
-                sys.cpu.soft_break = true;
-
-                7
+                CPU::interrupt(sys, CPU::ADDR_IRQ, true)?;
             }
      
 
@@ -402,30 +360,24 @@ impl OpCode {
                 sys.cpu.set_reg(target_a, value);
                 sys.cpu.set_reg(target_b, value);
                 sys.cpu.update_flags(value);
-                0 // TODO: ??
             }
 
             OpCode::StoreHack(source_a, source_b) => {
-                let addr = resolve_addr(sys, address_mode)?;
+                let addr = resolve_addr_for_write(sys, address_mode)?;
                 let value_a = sys.cpu.get_reg(source_a);
                 let value_b = sys.cpu.get_reg(source_b);
                 let value = value_a & value_b;
                 sys.write_byte(addr, value)?;
-                0 // TODO: ??
             }
 
             OpCode::DecCmpHack => {
                 let value_r = sys.cpu.a;
 
-                let addr = resolve_addr(sys, &address_mode)?;
-                let value_m = sys.read_byte(addr)?.wrapping_sub(1);
+                let value_m = read_modify_write(sys, address_mode, |_, v| v.wrapping_sub(1))?;
                 sys.cpu.carry = value_r >= value_m;
                 let val = value_r.wrapping_sub(value_m);
                 sys.cpu.update_flags(val);
                 sys.cpu.zero = value_r == value_m;
-                sys.write_byte(addr, value_m)?;
-
-                2
             }
 
             OpCode::IncSubHack => {
@@ -433,58 +385,52 @@ impl OpCode {
 
                 let value_r = sys.cpu.a;
 
-                let addr = resolve_addr(sys, &address_mode)?;
-                let value_m = sys.read_byte(addr)?.wrapping_add(1);
-
-                sys.write_byte(addr, value_m)?;
+                let value_m = read_modify_write(sys, address_mode, |_, v| v.wrapping_add(1))?;
 
                 cpu_sub(sys, value_m, value_r, sys.cpu.carry);
-
-                2
             }
 
             OpCode::ShiftLeftOrHack => {
                 // M = C <- [76543210] <- 0, A OR M -> A
-                let addr = resolve_addr(sys, &address_mode)?;
-                let value_m = sys.read_byte(addr)?;
-                let (shifted, carry) = shift_left(value_m);
-                sys.cpu.carry = carry;
-                sys.write_byte(addr, shifted)?;
+                let shifted = read_modify_write(sys, address_mode, |cpu, v| {
+                    let (shifted, carry) = shift_left(v);
+                    cpu.carry = carry;
+                    shifted
+                })?;
                 sys.cpu.a |= shifted;
-
-                2
+                sys.cpu.update_flags(sys.cpu.a);
             },
 
             OpCode::RotLeftAndHack => {
                 // M = C <- [76543210] <- C, A AND M -> A
-                let addr = resolve_addr(sys, &address_mode)?;
-                let value_m = sys.read_byte(addr)?;
-                let (value, carry) = rot_left(value_m, sys.cpu.carry);
-                sys.cpu.carry = carry;
+                let value = read_modify_write(sys, address_mode, |cpu, v| {
+                    let (value, carry) = rot_left(v, cpu.carry);
+                    cpu.carry = carry;
+                    value
+                })?;
                 sys.cpu.a &= value;
-                sys.write_byte(addr, value)?;
-                sys.cpu.update_flags(value);
-                2
+                sys.cpu.update_flags(sys.cpu.a);
             }
 
             OpCode::ShiftRightOrHack => {
                 // Lsr + Eor, M = 0 -> [76543210] -> C, A EOR M -> A
-                let addr = resolve_addr(sys, &address_mode)?;
-                let value_m = sys.read_byte(addr)?;
-                let (shifted, carry) = shift_right(value_m);
-                sys.cpu.carry = carry;
-                sys.write_byte(addr, shifted)?;
+                let shifted = read_modify_write(sys, address_mode, |cpu, v| {
+                    let (shifted, carry) = shift_right(v);
+                    cpu.carry = carry;
+                    shifted
+                })?;
                 sys.cpu.a ^= shifted;
-                2
+                sys.cpu.update_flags(sys.cpu.a);
             }
 
             OpCode::RotRightAddHack => {
                 // Ror + Adc, M = C -> [76543210] -> C, A + M + C -> A, C
-                let addr = resolve_addr(sys, &address_mode)?;
-                let value_m = sys.read_byte(addr)?;
-                let (rhs_val, rot_carry) = rot_right(value_m, sys.cpu.carry);
-                // sys.cpu.carry = carry;
-                sys.write_byte(addr, rhs_val)?;
+                let mut rot_carry = false;
+                let rhs_val = read_modify_write(sys, address_mode, |cpu, v| {
+                    let (value, carry) = rot_right(v, cpu.carry);
+                    rot_carry = carry;
+                    value
+                })?;
 
                 let ack_val = sys.cpu.a;
                 let carry = if rot_carry {1} else {0};
@@ -504,17 +450,134 @@ impl OpCode {
 
                 sys.cpu.overflow = if overflow {false} else {org_sign != sys.cpu.sign};
                 // TODO:
-                2
+            }
+
+            OpCode::StoreHighHack(source_a, source_b) => {
+                let value = sys.cpu.get_reg(source_a) & sys.cpu.get_reg(source_b);
+                store_high_and(sys, address_mode, value)?;
+            }
+
+            OpCode::StackStoreHighHack => {
+                sys.cpu.sp = sys.cpu.a & sys.cpu.x;
+                store_high_and(sys, address_mode, sys.cpu.sp)?;
+            }
+
+            OpCode::LoadStackHack => {
+                let addr = resolve_addr(sys, address_mode)?;
+                let value = sys.read_byte(addr)? & sys.cpu.sp;
+                sys.cpu.a = value;
+                sys.cpu.x = value;
+                sys.cpu.sp = value;
+                sys.cpu.update_flags(value);
+            }
+
+            OpCode::AndCarryHack => {
+                let addr = resolve_addr(sys, address_mode)?;
+                sys.cpu.a &= sys.read_byte(addr)?;
+                sys.cpu.update_flags(sys.cpu.a);
+                sys.cpu.carry = sys.cpu.sign;
+            }
+
+            OpCode::AndShiftRightHack => {
+                let addr = resolve_addr(sys, address_mode)?;
+                let (value, carry) = shift_right(sys.cpu.a & sys.read_byte(addr)?);
+                sys.cpu.a = value;
+                sys.cpu.carry = carry;
+                sys.cpu.update_flags(value);
+            }
+
+            OpCode::AndRotRightHack => {
+                let addr = resolve_addr(sys, address_mode)?;
+                let (value, _) = rot_right(sys.cpu.a & sys.read_byte(addr)?, sys.cpu.carry);
+                sys.cpu.a = value;
+                sys.cpu.update_flags(value);
+                sys.cpu.carry = value & 0b0100_0000 != 0;
+                sys.cpu.overflow = ((value >> 6) ^ (value >> 5)) & 1 != 0;
+            }
+
+            OpCode::AndSubXHack => {
+                let addr = resolve_addr(sys, address_mode)?;
+                let value_m = sys.read_byte(addr)?;
+                let value_r = sys.cpu.a & sys.cpu.x;
+                sys.cpu.carry = value_r >= value_m;
+                sys.cpu.x = value_r.wrapping_sub(value_m);
+                sys.cpu.update_flags(sys.cpu.x);
+            }
+
+            OpCode::MagicAndHack => {
+                // (A OR magic) AND X AND M -> A
+                let addr = resolve_addr(sys, address_mode)?;
+                let value = (sys.cpu.a | sys.opts.magic_constant) & sys.cpu.x & sys.read_byte(addr)?;
+                sys.cpu.a = value;
+                sys.cpu.update_flags(value);
+            }
+
+            OpCode::MagicLoadHack => {
+                // (A OR magic) AND M -> A, X
+                let addr = resolve_addr(sys, address_mode)?;
+                let value = (sys.cpu.a | sys.opts.magic_constant) & sys.read_byte(addr)?;
+                sys.cpu.a = value;
+                sys.cpu.x = value;
+                sys.cpu.update_flags(value);
+            }
+
+            OpCode::Jam => {
+                // The CPU gets stuck reading the bus, keep executing the same opcode
+                dummy_read_pc(sys)?;
+                sys.read_byte(0xffffu16)?;
+                sys.cpu.pc = sys.cpu.pc - 1;
             }
 
             // op => panic!("opcode {op:?} is not implemented")
         };
 
-        sys.cycles += cycles;
         Ok(sys.cycles - cycles_start)
     }
 }
 
+/// Reads the operand, writes it back unmodified while `modify` runs, then writes the result, which is returned
+fn read_modify_write<F>(sys: &mut System, address_mode: &AddressMode, modify: F) -> anyhow::Result<u8>
+    where F: FnOnce(&mut CPU, u8) -> u8
+{
+    let addr = resolve_addr_for_write(sys, address_mode)?;
+    let value = sys.read_byte(addr)?;
+    sys.write_byte(addr, value)?;
+    let value = modify(&mut sys.cpu, value);
+    sys.write_byte(addr, value)?;
+    Ok(value)
+}
+
+/// SHA, SHX, SHY and TAS AND the value with the high byte of the base address plus one.
+/// When the index crosses a page, the stored value also replaces the high byte of the target address.
+fn store_high_and(sys: &mut System, address_mode: &AddressMode, value: u8) -> anyhow::Result<()> {
+    let addr = resolve_addr_for_write(sys, address_mode)?;
+    let index = match address_mode {
+        AddressMode::Absolute(Some(reg)) | AddressMode::Indirect(Some(reg)) => sys.cpu.get_reg(reg),
+        _ => 0,
+    };
+    let base = Addr(addr.0.wrapping_sub(index as u16));
+
+    let value = value & base.msb().wrapping_add(1);
+    let addr = if addr.same_page_as(base) {addr} else {Addr::from_bytes(value, addr.lsb())};
+    sys.write_byte(addr, value)
+}
+
+/// Shifts and rotates work either on the accumulator or on memory
+fn shift_op<F>(sys: &mut System, address_mode: &AddressMode, modify: F) -> anyhow::Result<u8>
+    where F: FnOnce(&mut CPU, u8) -> u8
+{
+    match address_mode {
+        AddressMode::Register(r) => {
+            dummy_read_pc(sys)?;
+            let value = sys.cpu.get_reg(r);
+            let value = modify(&mut sys.cpu, value);
+            sys.cpu.set_reg(r, value);
+            Ok(value)
+        },
+        _ => read_modify_write(sys, address_mode, modify)
+    }
+}
+
 fn cpu_sub(sys: &mut System, sub: u8, ack: u8, carry: bool) {
 
     let carry = if carry {0} else {1};
@@ -68,7 +68,8 @@ pub(crate) fn load(sys: &mut System) -> anyhow::Result<(OpCode, AddressMode)> {
                 => Ok((OpCode::NoOp, AddressMode::Zero(Some(Register::X)))),
         0x1a | 0x3a | 0x5a | 0x7a | 0xda | 0xfa 
                 => Ok((OpCode::NoOp, AddressMode::Implied)),
-        0x80 => Ok((OpCode::NoOp, AddressMode::Immediate)),
+        0x80 | 0x82 | 0x89 | 0xc2 | 0xe2
+                => Ok((OpCode::NoOp, AddressMode::Immediate)),
         0x1c | 0x3c | 0x5c | 0x7c | 0xdc | 0xfc 
                 => Ok((OpCode::NoOp, AddressMode::Absolute(Some(Register::X)))),
 
@@ -82,15 +83,44 @@ pub(crate) fn load(sys: &mut System) -> anyhow::Result<(OpCode, AddressMode)> {
         0x83 => Ok((OpCode::StoreHack(Register::A, Register::X), AddressMode::Indirect(Some(Register::X)))),
         0x87 => Ok((OpCode::StoreHack(Register::A, Register::X), AddressMode::Zero(None))),
         0x8f => Ok((OpCode::StoreHack(Register::A, Register::X), AddressMode::Absolute(None))),
-        0x93 => Ok((OpCode::StoreHack(Register::A, Register::X), AddressMode::Indirect(Some(Register::Y)))),
         0x97 => Ok((OpCode::StoreHack(Register::A, Register::X), AddressMode::Zero(Some(Register::Y)))),
-        // SHA:
+
+        // SHA, SHX, SHY:
         /*
         Stores A AND X AND (high-byte of addr. + 1) at addr.
         unstable: sometimes 'AND (H+1)' is dropped, page boundary crossings may not work 
                   (with the high-byte of the value used as the high-byte of the address)
         */
-        // 0x9f => Ok((OpCode::LoadHack(Register::A, Register::X), AddressMode::Absolute(Some(Register::Y)))),
+        0x93 => Ok((OpCode::StoreHighHack(Register::A, Register::X), AddressMode::Indirect(Some(Register::Y)))),
+        0x9f => Ok((OpCode::StoreHighHack(Register::A, Register::X), AddressMode::Absolute(Some(Register::Y)))),
+        0x9e => Ok((OpCode::StoreHighHack(Register::X, Register::X), AddressMode::Absolute(Some(Register::Y)))),
+        0x9c => Ok((OpCode::StoreHighHack(Register::Y, Register::Y), AddressMode::Absolute(Some(Register::X)))),
+
+        // TAS, A AND X -> SP, then stored like SHA
+        0x9b => Ok((OpCode::StackStoreHighHack, AddressMode::Absolute(Some(Register::Y)))),
+
+        // LAS, M AND SP -> A, X, SP
+        0xbb => Ok((OpCode::LoadStackHack, AddressMode::Absolute(Some(Register::Y)))),
+
+        // ANC, And + carry from bit 7
+        0x0b | 0x2b => Ok((OpCode::AndCarryHack, AddressMode::Immediate)),
+
+        // ALR, And + Lsr
+        0x4b => Ok((OpCode::AndShiftRightHack, AddressMode::Immediate)),
+
+        // ARR, And + Ror, with C and V taken from bits 6 and 5 of the result
+        0x6b => Ok((OpCode::AndRotRightHack, AddressMode::Immediate)),
+
+        // SBX, (A AND X) - M -> X, without borrow
+        0xcb => Ok((OpCode::AndSubXHack, AddressMode::Immediate)),
+
+        // ANE and LXA, unstable: A is ORed with a magic constant that depends on the chip
+        0x8b => Ok((OpCode::MagicAndHack, AddressMode::Immediate)),
+        0xab => Ok((OpCode::MagicLoadHack, AddressMode::Immediate)),
+
+        // JAM, KIL: the CPU locks up until reset
+        0x02 | 0x12 | 0x22 | 0x32 | 0x42 | 0x52 | 0x62 | 0x72 | 0x92 | 0xb2 | 0xd2 | 0xf2
+                => Ok((OpCode::Jam, AddressMode::Implied)),
 
         // Nop + Sub 🤮
         0xeb => Ok((OpCode::Sub, AddressMode::Immediate)),
@@ -319,8 +349,6 @@ pub(crate) fn load(sys: &mut System) -> anyhow::Result<(OpCode, AddressMode)> {
         0x8a => Ok((OpCode::Transfer(Register::X,  Register::A ), AddressMode::Implied)),
         0x9a => Ok((OpCode::Transfer(Register::X,  Register::SP), AddressMode::Implied)),
         0x98 => Ok((OpCode::Transfer(Register::Y,  Register::A ), AddressMode::Implied)),
-                    
-        instr => anyhow::bail!("instruction not implemented: 0x{instr:02x}")
     }
 }
 
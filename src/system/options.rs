@@ -4,6 +4,15 @@ pub struct Options {
 
 
     pub sprite_order_overlay: bool,
+
+    /// Only show 8 sprites per line like the hardware, disabling it removes flicker
+    pub sprite_limit: bool,
+
+    /// Host sample rate to generate audio for, `None` disables audio output
+    pub sample_rate: Option<u32>,
+
+    /// Constant ORed into A by the unstable ANE and LXA opcodes, it differs between CPUs
+    pub magic_constant: u8,
 }
 
 impl Default for Options {
@@ -12,6 +21,9 @@ impl Default for Options {
             dump_ops: false,
             history_len: 0,
             sprite_order_overlay: false,
+            sprite_limit: true,
+            sample_rate: None,
+            magic_constant: 0xee,
         }
     }
 }
\ No newline at end of file
@@ -1,14 +1,17 @@
-use crate::system::addr::Addr;
-
 use super::System;
+use crate::mappers::Mirroring;
 use draw::draw;
 use registers::{Control, Status, Mask};
 
+mod background;
 mod draw;
+mod open_bus;
+pub mod palette;
 mod registers;
+mod sprites;
 
 
-const PRE_RENDER_LINE: u16 = 245;
+const PRE_RENDER_LINE: u16 = 261;
 const POST_RENDER_LINE: u16 = 240;
 
 
@@ -20,10 +23,19 @@ pub struct PPU {
     pub status: Status,
     pub(crate) oam_addr: u8,
     data: u8,
-    scroll: [u8; 2],
-    addr: u16,
-    scroll_y: bool,
-    addr_lsb: bool,
+    io_latch: open_bus::OpenBus,
+
+    // Internal scroll registers ("loopy" v, t, x and w)
+    //
+    // yyy NN YYYYY XXXXX
+    // ||| || ||||| +++++-- coarse X scroll
+    // ||| || +++++-------- coarse Y scroll
+    // ||| ++-------------- nametable select
+    // +++----------------- fine Y scroll
+    v: u16,
+    t: u16,
+    fine_x: u8,
+    w: bool,
 
     pub scan_line: u16, // column
     pub scan_row: u16,
@@ -35,9 +47,16 @@ pub struct PPU {
 
     pub vram: Vec<u8>,
     pub palette: [u8; 32],
+    /// RGB colors for every palette value and emphasis combination
+    pub colors: Vec<u32>,
     pub bg_patterns: [u16; 2],
-    pub bg_palettes: [u8; 2],
+    pub bg_palettes: [u16; 2],
+    bg_next_tile: u8,
+    bg_next_attr: u8,
+    bg_next_pattern: [u8; 2],
 
+    /// Sprites found for the next line, with their OAM index
+    secondary_oam: Vec<(u8, [u8; 4])>,
     pub sprite_outputs: Vec<[u8; 7]>,
 }
 
@@ -60,19 +79,25 @@ impl PPU {
             status: Status::default(),
             oam_addr: 0,
             data: 0,
-            scroll: [0; 2],
-            addr: 0,
-            scroll_y: false,
-            addr_lsb: false,
+            io_latch: open_bus::OpenBus::default(),
+            v: 0,
+            t: 0,
+            fine_x: 0,
+            w: false,
             scan_line: 21,
             scan_row: 0,
             frame_buffer: [[0u32; 256]; 240],
             vram: vec![0; 0x4000],
             palette: [0; 32],
+            colors: palette::with_emphasis(&Self::PALETTE_COLORS),
             bg_patterns: [0; 2],
             bg_palettes: [0; 2],
+            bg_next_tile: 0,
+            bg_next_attr: 0,
+            bg_next_pattern: [0; 2],
 
-            sprite_outputs: Vec::with_capacity(8)
+            secondary_oam: Vec::with_capacity(64),
+            sprite_outputs: Vec::with_capacity(64)
         }
     }
 
@@ -80,6 +105,48 @@ impl PPU {
     //     Self::palette_colors[self.mono_frame_buffer[y][x] as usize]
     // }
 
+    fn rendering_enabled(&self) -> bool {
+        self.mask.enable_bg || self.mask.enable_fg
+    }
+
+    fn increment_coarse_x(&mut self) {
+        if self.v & 0x001f == 31 {
+            // wrap around into the horizontally adjacent nametable
+            self.v &= !0x001f;
+            self.v ^= 0x0400;
+        } else {
+            self.v += 1;
+        }
+    }
+
+    fn increment_y(&mut self) {
+        if self.v & 0x7000 != 0x7000 {
+            self.v += 0x1000;
+            return
+        }
+        self.v &= !0x7000;
+        let mut coarse_y = (self.v & 0x03e0) >> 5;
+        if coarse_y == 29 {
+            // last row of tiles, wrap into the vertically adjacent nametable
+            coarse_y = 0;
+            self.v ^= 0x0800;
+        } else if coarse_y == 31 {
+            // out of bounds (attribute table), wraps without switching
+            coarse_y = 0;
+        } else {
+            coarse_y += 1;
+        }
+        self.v = (self.v & !0x03e0) | (coarse_y << 5);
+    }
+
+    fn copy_horizontal(&mut self) {
+        self.v = (self.v & !0x041f) | (self.t & 0x041f);
+    }
+
+    fn copy_vertical(&mut self) {
+        self.v = (self.v & !0x7be0) | (self.t & 0x7be0);
+    }
+
 }
 
 pub(crate) fn tick(sys: &mut System) -> anyhow::Result<()> {
@@ -102,6 +169,7 @@ pub(crate) fn tick(sys: &mut System) -> anyhow::Result<()> {
     if col == 0 {
         if row == PRE_RENDER_LINE {
             sys.ppu.status.sprite_zero_hit = false;
+            sys.ppu.status.sprite_overflow = false;
             sys.ppu.status.vertical_blank = false;
         }
 
@@ -117,11 +185,37 @@ pub(crate) fn tick(sys: &mut System) -> anyhow::Result<()> {
 
 
 
-    if row <= 239 {
-        if col >= 1 && col < 257 {
-            draw(sys, col as usize - 1, row as usize)?;
+    let rendering = sys.ppu.rendering_enabled() && (row <= 239 || row == PRE_RENDER_LINE);
+    if rendering {
+        background::fetch(sys, col)?;
+    }
+
+    if row <= 239 && (1..257).contains(&col) {
+        draw(sys, col as usize - 1, row as usize)?;
+    }
+
+    if rendering {
+        if ((1..257).contains(&col) && col % 8 == 0) || col == 328 || col == 336 {
+            sys.ppu.increment_coarse_x();
+        }
+        if col == 256 {
+            if row <= 239 {
+                sprites::evaluate(sys, row);
+            } else {
+                // no evaluation happens on the pre-render line, so line 0 never has sprites
+                sys.ppu.secondary_oam.clear();
+            }
+        }
+        if (257..=320).contains(&col) {
+            sprites::fetch(sys, col, row)?;
+        }
+
+        if col == 256 {
+            sys.ppu.increment_y();
         } else if col == 257 {
-            // eprintln!();
+            sys.ppu.copy_horizontal();
+        } else if row == PRE_RENDER_LINE && (280..=304).contains(&col) {
+            sys.ppu.copy_vertical();
         }
     }
     Ok(())
@@ -129,9 +223,12 @@ pub(crate) fn tick(sys: &mut System) -> anyhow::Result<()> {
 }
 
 pub(crate) fn write(sys: &mut System, address: u8, value: u8) -> anyhow::Result<()> {
+    sys.ppu.io_latch.drive(value, 0xff, sys.cycles);
+
     match address {
         0 => {
             sys.ppu.control = value.into();
+            sys.ppu.t = (sys.ppu.t & !0x0c00) | ((value as u16 & 0b11) << 10);
             // eprintln!("PPU Control set from {value:02x} ({value:08b}) to {:?}", sys.ppu.control);
             
         }
@@ -139,64 +236,52 @@ pub(crate) fn write(sys: &mut System, address: u8, value: u8) -> anyhow::Result<
             sys.ppu.mask = value.into();
             
         }
-        // 2 => {
-        //     sys.ppu.status = value;
-        // }
+        // PPUSTATUS is read-only, the write only reaches the latch
+        2 => {}
         3 => {
             sys.ppu.oam_addr = value;
             // eprintln!("Wrote OAM Addr: {value:04x}");
         }
         4 => {
             sys.oam[sys.ppu.oam_addr as usize] = value;
-            eprintln!("Wrote OAM Data: {value:04x}");
-            // sys.ppu.oam_data = value;
-            // panic!()
-            let _ = sys.ppu.oam_addr.wrapping_add(1);
+            sys.ppu.oam_addr = sys.ppu.oam_addr.wrapping_add(1);
         }
         5 => {
-            if sys.ppu.scroll_y {
-                sys.ppu.scroll[1] = value;
+            if sys.ppu.w {
+                // fine Y and coarse Y
+                sys.ppu.t = (sys.ppu.t & !0x73e0) | ((value as u16 & 0x07) << 12) | ((value as u16 & 0xf8) << 2);
             } else {
-                sys.ppu.scroll[0] = value;
-                sys.ppu.scroll[1] = 0;
+                // coarse X and fine X
+                sys.ppu.t = (sys.ppu.t & !0x001f) | (value as u16 >> 3);
+                sys.ppu.fine_x = value & 0x07;
             }
-            sys.ppu.scroll_y = !sys.ppu.scroll_y;
-            // eprintln!("PPU scroll set to {} by {}!", sys.ppu.scroll[0], sys.ppu.scroll[1]);
+            sys.ppu.w = !sys.ppu.w;
         }
         6 => {
-            if sys.ppu.addr_lsb {
-                sys.ppu.addr |= value as u16;
-                
+            if sys.ppu.w {
+                sys.ppu.t = (sys.ppu.t & 0xff00) | value as u16;
+                sys.ppu.v = sys.ppu.t;
+                sys.cart.mapper.ppu_address((sys.ppu.v & 0x3fff).into());
             } else {
-                sys.ppu.addr = (value as u16) << 8;
+                // bit 14 is cleared by the first write
+                sys.ppu.t = (sys.ppu.t & 0x00ff) | ((value as u16 & 0x3f) << 8);
             }
-            sys.ppu.addr_lsb = !sys.ppu.addr_lsb;
-            // eprintln!("PPU address set to 0x{:04x}!", sys.ppu.addr);
+            sys.ppu.w = !sys.ppu.w;
+            // eprintln!("PPU address set to 0x{:04x}!", sys.ppu.v);
         }
         7 => {
-            if sys.ppu.addr < 0x2000 {
+            let vaddr = sys.ppu.v & 0x3fff;
+            sys.cart.mapper.ppu_address(vaddr.into());
+            if vaddr < 0x2000 {
                 // Writing to CHR-RAM. Let's just hope it's RAM...
-                sys.cart.mapper.ppu_write(sys.ppu.addr.into(), value)?;
-                // sys.ppu.vram[sys.ppu.addr as usize] = value;
-            } else if sys.ppu.addr < 0x3000 {
-                if sys.ppu.addr == 0x27a0 {
-                    println!("Writing {value:02x} to {}!", Addr(sys.ppu.addr));
-                }
-                let addr = mirrored_addr(sys, sys.ppu.addr - 0x2000);
-
-                sys.ppu.vram[addr as usize] = value;
-
-            } else if sys.ppu.addr < 0x3f00 {
-                panic!("tried to write {value:02x} to PPU address {:04x}", sys.ppu.addr);
-            } else if sys.ppu.addr < 0x3fff {
-                let mut addr = (sys.ppu.addr - 0x3f00) % 0x20;
-                if addr % 4 == 0 {
-                    addr &= 0x0f
+                sys.cart.mapper.ppu_write(vaddr.into(), value)?;
+            } else if vaddr < 0x3f00 {
+                if !sys.cart.mapper.nametable_write(vaddr.into(), value) {
+                    let addr = mirrored_addr(sys, (vaddr - 0x2000) % 0x1000);
+                    sys.ppu.vram[addr as usize] = value;
                 }
-                // eprintln!("Wrote palette entry {value:02x} to {:04x}", addr);
-                sys.ppu.palette[addr as usize] = value;
             } else {
-                panic!("tried to write {value:02x} to PPU address {:04x}", sys.ppu.addr);
+                sys.ppu.palette[palette_index(vaddr)] = value & 0x3f;
             }
             bump_addr(sys);
         }
@@ -208,74 +293,185 @@ pub(crate) fn write(sys: &mut System, address: u8, value: u8) -> anyhow::Result<
 }
 
 fn bump_addr(sys: &mut System) {
-    sys.ppu.addr += sys.ppu.control.vram_incr
+    let row = sys.ppu.scan_row;
+    if sys.ppu.rendering_enabled() && (row <= 239 || row == PRE_RENDER_LINE) {
+        // PPUDATA accesses during rendering trigger both scroll increments at once
+        sys.ppu.increment_coarse_x();
+        sys.ppu.increment_y();
+    } else {
+        sys.ppu.v = (sys.ppu.v + sys.ppu.control.vram_incr) & 0x7fff;
+    }
 }
 
-fn mirrored_addr(sys: &System, base: u16) -> u16 {
-    let vertical_mirroring = sys.cart.header.vertical_mirroring;
-    if vertical_mirroring {
-        // eprintln!("Writing to {:04x} wrapped from {:04x}", base % 0x800, base);
-        base % 0x800
+/// Reads from the PPU address space, letting the mapper see the address.
+fn read_vram(sys: &mut System, addr: u16) -> anyhow::Result<u8> {
+    let addr = addr & 0x3fff;
+    sys.cart.mapper.ppu_address(addr.into());
+    if addr < 0x2000 {
+        sys.cart.mapper.ppu_read(addr.into())
+    } else if let Some(value) = sys.cart.mapper.nametable_read(addr.into()) {
+        Ok(value)
     } else {
-        if base < 0x800 {
-            // All addresses are within table A
-            base % 0x400
-        } else {
-            // All addresses are within table B
-            0x400 + (base % 0x400)
-        }
+        Ok(sys.ppu.vram[mirrored_addr(sys, (addr - 0x2000) % 0x1000) as usize])
+    }
+}
+
+/// Palette RAM is 32 bytes mirrored up to $3FFF, and the backdrop entries of
+/// the sprite palettes share their memory with the background ones.
+fn palette_index(vaddr: u16) -> usize {
+    let addr = (vaddr as usize - 0x3f00) % 0x20;
+    if addr % 4 == 0 {addr & 0x0f} else {addr}
+}
+
+fn mirrored_addr(sys: &System, base: u16) -> u16 {
+    let mirroring = sys.cart.mapper.mirroring()
+        .unwrap_or_else(|| Mirroring::from_header(&sys.cart.header));
+    match mirroring {
+        Mirroring::Vertical => base % 0x800,
+        // $2000/$2400 are table A, $2800/$2C00 table B
+        Mirroring::Horizontal => (base / 0x800) * 0x400 + (base % 0x400),
+        Mirroring::OneScreenLower => base % 0x400,
+        Mirroring::OneScreenUpper => 0x400 + (base % 0x400),
+        Mirroring::FourScreen => base,
+        Mirroring::Custom(pages) => (pages[base as usize / 0x400] as u16 % 4) * 0x400 + (base % 0x400),
     }
 }
 
 pub(crate) fn read(sys: &mut System, address: u8) -> anyhow::Result<u8> {
-    let value = match address {
-        0 => panic!("tried to read from PPU CONTROL"),
-        1 => panic!("tried to read from PPU MASK"),
+    let open_bus = sys.ppu.io_latch.value(sys.cycles);
+
+    // the bits each register drives, the rest read back the I/O latch
+    let (value, driven) = match address {
+        // write-only registers
+        0 | 1 | 3 | 5 | 6 => (0, 0),
         2 => {
-            let value = (&sys.ppu.status).into();
+            let value: u8 = (&sys.ppu.status).into();
              // clear vblank (??)
             sys.ppu.status.vertical_blank = false;
 
-            // clear address latch
-            sys.ppu.addr_lsb = false;
-            sys.ppu.scroll_y = false;
+            // clear the write toggle
+            sys.ppu.w = false;
 
             // eprintln!("Read from PPUSTATUS: {value:08b}");
 
-            value
+            (value, 0b1110_0000)
         }
-        3 => panic!("tried to read from OAM ADDR"),
         4 => {
-
-            panic!("tried to read from OAM DATA")
+            let value = sys.oam[sys.ppu.oam_addr as usize];
+            if sys.ppu.oam_addr % 4 == 2 {
+                // bits 2-4 of the sprite attributes don't exist
+                (value & 0b1110_0011, 0xff)
+            } else {
+                (value, 0xff)
+            }
         },
-        5 => panic!("tried to read from PPU SCROLL"), //sys.ppu.scroll,
-        6 => panic!("tried to read from PPU ADDRESS"), //sys.ppu.addr,
         7 => {
-            let value = sys.ppu.data;
-
-            sys.ppu.data = if sys.ppu.addr < 0x2000 {
-                // Reading from to CHR-RAM
-                //sys.ppu.vram[sys.ppu.addr as usize]
-                sys.cart.mapper.ppu_read(sys.ppu.addr.into())?
-            } else if sys.ppu.addr < 0x3000 {
-                let addr = mirrored_addr(sys, sys.ppu.addr - 0x2000);
-
-                sys.ppu.vram[addr as usize]
-            } else if sys.ppu.addr < 0x3fff {
-                sys.ppu.vram[sys.ppu.addr as usize]
+            let vaddr = sys.ppu.v & 0x3fff;
+            let value = if vaddr < 0x3f00 {
+                let value = sys.ppu.data;
+                sys.ppu.data = read_vram(sys, vaddr)?;
+                (value, 0xff)
             } else {
-                panic!("Tried to read from PPUDATA {:04x}", sys.ppu.addr)
+                // Palette reads skip the buffer, which gets the nametable
+                // byte "underneath" the palette instead
+                sys.ppu.data = read_vram(sys, vaddr - 0x1000)?;
+                let value = sys.ppu.palette[palette_index(vaddr)];
+                // palette entries are 6 bits wide
+                (if sys.ppu.mask.greyscale {value & 0x30} else {value}, 0x3f)
             };
 
             bump_addr(sys);
 
             value
         }
-        _ => panic!("invalid PPU address {address}"),
+        _ => anyhow::bail!("invalid PPU address {address}"),
     };
-    if value != 0 {
-        // eprintln!("Read from PPU ${address}: 0x{value:02x}!");
+
+    sys.ppu.io_latch.drive(value, driven, sys.cycles);
+    Ok((value & driven) | (open_bus & !driven))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::system::{System, options::Options};
+
+    fn set_addr(sys: &mut System, addr: u16) {
+        super::write(sys, 6, (addr >> 8) as u8).unwrap();
+        super::write(sys, 6, addr as u8).unwrap();
+    }
+
+    #[test]
+    fn data_reads_are_buffered() {
+        let mut sys = System::new(Options::default()).unwrap();
+        set_addr(&mut sys, 0x2400);
+        super::write(&mut sys, 7, 0x12).unwrap();
+        super::write(&mut sys, 7, 0x34).unwrap();
+
+        // $3000-$3EFF mirrors the nametables
+        set_addr(&mut sys, 0x3400);
+        super::read(&mut sys, 7).unwrap();
+        assert_eq!(super::read(&mut sys, 7).unwrap(), 0x12);
+        assert_eq!(super::read(&mut sys, 7).unwrap(), 0x34);
+    }
+
+    #[test]
+    fn palette_reads_skip_the_buffer() {
+        let mut sys = System::new(Options::default()).unwrap();
+        set_addr(&mut sys, 0x2f10);
+        super::write(&mut sys, 7, 0x56).unwrap();
+        set_addr(&mut sys, 0x3f00);
+        super::write(&mut sys, 7, 0x2a).unwrap();
+
+        // $3F10 mirrors the backdrop at $3F00
+        set_addr(&mut sys, 0x3f10);
+        assert_eq!(super::read(&mut sys, 7).unwrap(), 0x2a);
+        assert_eq!(sys.ppu.data, 0x56);
+    }
+
+    #[test]
+    fn oam_dma_starts_at_oam_addr() {
+        let mut sys = System::new(Options::default()).unwrap();
+        for i in 0..=255u8 {
+            sys.ram[0x200 + i as usize] = i;
+        }
+        super::write(&mut sys, 3, 0x10).unwrap();
+        sys.write_byte(0x4014u16, 0x02).unwrap();
+
+        assert_eq!(sys.oam[0x10], 0x00);
+        assert_eq!(sys.oam[0xff], 0xef);
+        // wraps around to the start of OAM
+        assert_eq!(sys.oam[0x00], 0xf0);
+        assert_eq!(sys.oam[0x0f], 0xff);
+    }
+
+    #[test]
+    fn oam_reads_mask_the_attributes() {
+        let mut sys = System::new(Options::default()).unwrap();
+        super::write(&mut sys, 3, 0x00).unwrap();
+        for _ in 0..4 {
+            super::write(&mut sys, 4, 0xff).unwrap();
+        }
+        super::write(&mut sys, 3, 0x01).unwrap();
+        assert_eq!(super::read(&mut sys, 4).unwrap(), 0xff);
+        super::write(&mut sys, 3, 0x02).unwrap();
+        assert_eq!(super::read(&mut sys, 4).unwrap(), 0xe3);
+    }
+
+    #[test]
+    fn write_only_registers_read_the_io_latch() {
+        let mut sys = System::new(Options::default()).unwrap();
+        super::write(&mut sys, 5, 0x5a).unwrap();
+        assert_eq!(super::read(&mut sys, 0).unwrap(), 0x5a);
+        assert_eq!(super::read(&mut sys, 6).unwrap(), 0x5a);
+
+        // PPUSTATUS only drives its top 3 bits
+        sys.ppu.status = 0x80.into();
+        assert_eq!(super::read(&mut sys, 2).unwrap(), 0x9a);
+        // and puts them on the latch
+        assert_eq!(super::read(&mut sys, 3).unwrap(), 0x9a);
+
+        // writing PPUSTATUS does nothing but drive the latch
+        super::write(&mut sys, 2, 0x33).unwrap();
+        assert_eq!(super::read(&mut sys, 5).unwrap(), 0x33);
     }
-    Ok(value)
-}
\ No newline at end of file
+}
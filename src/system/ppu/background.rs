@@ -0,0 +1,84 @@
+use crate::system::System;
+
+use super::read_vram;
+
+
+/// Runs one dot of the background fetch pipeline: shifts the pattern and
+/// palette registers, reloads them every 8 dots and performs the nametable,
+/// attribute and pattern fetches for the next tile.
+pub(super) fn fetch(sys: &mut System, col: u16) -> anyhow::Result<()> {
+    let fetching = (1..=256).contains(&col) || (321..=336).contains(&col);
+    let shifting = (2..=257).contains(&col) || (322..=337).contains(&col);
+
+    if shifting {
+        let ppu = &mut sys.ppu;
+        ppu.bg_patterns[0] <<= 1;
+        ppu.bg_patterns[1] <<= 1;
+        ppu.bg_palettes[0] <<= 1;
+        ppu.bg_palettes[1] <<= 1;
+
+        if (col - 1) % 8 == 0 {
+            reload(sys);
+        }
+    }
+
+    let v = sys.ppu.v;
+    if fetching {
+        match (col - 1) % 8 {
+            0 => {
+                sys.ppu.bg_next_tile = read_vram(sys, 0x2000 | (v & 0x0fff))?;
+            }
+            2 => {
+                let at_addr = 0x23c0 | (v & 0x0c00) | ((v >> 4) & 0x38) | ((v >> 2) & 0x07);
+                let attributes = read_vram(sys, at_addr)?;
+                // each attribute byte covers 4x4 tiles, two bits per 2x2 quadrant
+                let shift = ((v >> 4) & 0b100) | (v & 0b10);
+                sys.ppu.bg_next_attr = (attributes >> shift) & 0x3;
+            }
+            4 => {
+                let addr = pattern_addr(sys);
+                sys.ppu.bg_next_pattern[0] = read_vram(sys, addr)?;
+            }
+            6 => {
+                let addr = pattern_addr(sys) + 8;
+                sys.ppu.bg_next_pattern[1] = read_vram(sys, addr)?;
+            }
+            _ => {}
+        }
+    } else if col == 337 || col == 339 {
+        // unused nametable fetches at the end of the line
+        read_vram(sys, 0x2000 | (v & 0x0fff))?;
+    }
+
+    Ok(())
+}
+
+/// The 2-bit background color index and palette under fine X, taken from
+/// the top of the shift registers.
+pub(super) fn pixel(sys: &System) -> u8 {
+    let ppu = &sys.ppu;
+    let mask = 0x8000u16 >> ppu.fine_x;
+    let bit = |reg: u16, value: u8| if reg & mask != 0 {value} else {0};
+
+    bit(ppu.bg_patterns[0], 1 << 0) |
+    bit(ppu.bg_patterns[1], 1 << 1) |
+    bit(ppu.bg_palettes[0], 1 << 2) |
+    bit(ppu.bg_palettes[1], 1 << 3)
+}
+
+fn reload(sys: &mut System) {
+    let ppu = &mut sys.ppu;
+    let [low, high] = ppu.bg_next_pattern;
+    ppu.bg_patterns[0] = (ppu.bg_patterns[0] & 0xff00) | low as u16;
+    ppu.bg_patterns[1] = (ppu.bg_patterns[1] & 0xff00) | high as u16;
+
+    // palette bits stay constant across the tile, so spread them over the byte
+    let fill = |set: bool| if set {0x00ff} else {0x0000};
+    ppu.bg_palettes[0] = (ppu.bg_palettes[0] & 0xff00) | fill(ppu.bg_next_attr & 0b01 != 0);
+    ppu.bg_palettes[1] = (ppu.bg_palettes[1] & 0xff00) | fill(ppu.bg_next_attr & 0b10 != 0);
+}
+
+fn pattern_addr(sys: &System) -> u16 {
+    let fine_y = (sys.ppu.v >> 12) & 0x07;
+    sys.ppu.control.pattern_base_bg + ((sys.ppu.bg_next_tile as u16) << 4) + fine_y
+}
@@ -0,0 +1,52 @@
+/// CPU cycles until a bit of the I/O latch that was driven high decays back
+/// to 0, roughly 600ms
+const DECAY_CYCLES: u64 = 1_073_864;
+
+/// The data bus between the CPU and the PPU registers. Its capacitance keeps
+/// the last value around, so write-only registers and unused bits read back
+/// whatever was last put on it until it fades.
+#[derive(Default)]
+pub(super) struct OpenBus {
+    value: u8,
+    /// CPU cycle at which each bit was last driven
+    refreshed: [u64; 8],
+}
+
+impl OpenBus {
+    /// Drives the bits in `mask` to `value`, the others keep decaying
+    pub(super) fn drive(&mut self, value: u8, mask: u8, now: u64) {
+        self.value = (self.value & !mask) | (value & mask);
+        for (bit, refreshed) in self.refreshed.iter_mut().enumerate() {
+            if mask & (1 << bit) != 0 {
+                *refreshed = now;
+            }
+        }
+    }
+
+    pub(super) fn value(&self, now: u64) -> u8 {
+        let mut value = self.value;
+        for (bit, refreshed) in self.refreshed.iter().enumerate() {
+            if now.saturating_sub(*refreshed) > DECAY_CYCLES {
+                value &= !(1 << bit);
+            }
+        }
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{OpenBus, DECAY_CYCLES};
+
+    #[test]
+    fn bits_decay_unless_driven() {
+        let mut bus = OpenBus::default();
+        bus.drive(0xff, 0xff, 0);
+        bus.drive(0x00, 0xe0, 1000);
+        bus.drive(0x1f, 0x1f, DECAY_CYCLES);
+
+        assert_eq!(bus.value(DECAY_CYCLES), 0x1f);
+        assert_eq!(bus.value(DECAY_CYCLES + 1000), 0x1f);
+        assert_eq!(bus.value(DECAY_CYCLES * 2 + 1), 0x00);
+    }
+}
@@ -0,0 +1,167 @@
+use std::{f32::consts::PI, fs, path::Path};
+
+use anyhow::{bail, Result};
+
+/// How much each set emphasis bit darkens the other two channels
+const ATTENUATION: f32 = 0.816328;
+
+/// Expands a 64 color palette to all 8 combinations of the PPUMASK emphasis
+/// bits, indexed by `emphasis << 6 | color`.
+pub fn with_emphasis(base: &[u32; 64]) -> Vec<u32> {
+    let mut colors = Vec::with_capacity(512);
+    for emphasis in 0..8u32 {
+        for (i, &color) in base.iter().enumerate() {
+            // $xE and $xF are forced black and stay that way
+            if emphasis == 0 || i % 16 >= 0xe {
+                colors.push(color);
+                continue
+            }
+            let mut channels = [(color >> 16) & 0xff, (color >> 8) & 0xff, color & 0xff];
+            for (channel, value) in channels.iter_mut().enumerate() {
+                let others = (emphasis & !(1 << channel)).count_ones();
+                *value = (*value as f32 * ATTENUATION.powi(others as i32)) as u32;
+            }
+            let [r, g, b] = channels;
+            colors.push(r << 16 | g << 8 | b);
+        }
+    }
+    colors
+}
+
+/// Reads a standard `.pal` file: 64 RGB triplets, or 512 when it includes
+/// the emphasis variants.
+pub fn load(path: &Path) -> Result<Vec<u32>> {
+    let bytes = fs::read(path)?;
+    let colors: Vec<u32> = bytes.chunks_exact(3)
+        .map(|rgb| (rgb[0] as u32) << 16 | (rgb[1] as u32) << 8 | rgb[2] as u32)
+        .collect();
+
+    match bytes.len() {
+        192 => Ok(with_emphasis(colors.as_slice().try_into()?)),
+        1536 => Ok(colors),
+        len => bail!("{} is not a palette, expected 192 or 1536 bytes but got {len}", path.display()),
+    }
+}
+
+/// Writes all 512 colors as a `.pal` file
+pub fn save(path: &Path, colors: &[u32]) -> Result<()> {
+    let bytes: Vec<u8> = colors.iter()
+        .flat_map(|color| [(color >> 16) as u8, (color >> 8) as u8, *color as u8])
+        .collect();
+    fs::write(path, bytes)?;
+    Ok(())
+}
+
+/// Generates a palette by decoding the composite signal of the NTSC PPU
+pub struct NtscPalette {
+    /// Hue rotation in degrees
+    pub hue: f32,
+    pub saturation: f32,
+    pub contrast: f32,
+    pub brightness: f32,
+    /// Gamma of the display, 2.2 leaves the decoded colors as they are
+    pub gamma: f32,
+}
+
+impl Default for NtscPalette {
+    fn default() -> Self {
+        Self {
+            hue: 0.0,
+            saturation: 1.0,
+            contrast: 1.0,
+            brightness: 0.0,
+            gamma: 2.2,
+        }
+    }
+}
+
+impl NtscPalette {
+    // Signal voltages for the 4 luma levels when the wave is low and high
+    const LOW: [f32; 4] = [0.228, 0.312, 0.552, 0.880];
+    const HIGH: [f32; 4] = [0.616, 0.840, 1.100, 1.100];
+    const BLACK: f32 = 0.312;
+    const WHITE: f32 = 1.100;
+
+    /// Signal level while an emphasis bit is active
+    const EMPHASIS_ATTENUATION: f32 = 0.746;
+
+    /// All 512 colors, indexed like [`with_emphasis`]
+    pub fn generate(&self) -> Vec<u32> {
+        (0..512).map(|pixel| self.color(pixel & 0x3f, pixel >> 6)).collect()
+    }
+
+    fn color(&self, pixel: usize, emphasis: usize) -> u32 {
+        let hue = (pixel & 0x0f) as i32;
+        // $xE and $xF output black
+        let level = if hue >= 0xe {1} else {pixel >> 4};
+
+        let low = if hue == 0 {Self::HIGH[level]} else {Self::LOW[level]};
+        let high = if hue >= 0xd {Self::LOW[level]} else {Self::HIGH[level]};
+
+        // the color generator is a square wave with 12 phases per pixel
+        let in_phase = |hue: i32, phase: i32| (hue + phase + 8) % 12 < 6;
+
+        let (mut y, mut i, mut q) = (0.0, 0.0, 0.0);
+        for phase in 0..12 {
+            let mut signal = if in_phase(hue, phase) {high} else {low};
+            let attenuated = (0..3).any(|bit| emphasis & (1 << bit) != 0 && in_phase(bit * 4, phase));
+            if attenuated {
+                signal *= Self::EMPHASIS_ATTENUATION;
+            }
+            let value = (signal - Self::BLACK) / (Self::WHITE - Self::BLACK) / 12.0;
+            let angle = PI / 6.0 * phase as f32 + self.hue.to_radians();
+            y += value;
+            i += value * angle.cos();
+            q += value * angle.sin();
+        }
+
+        let y = y * self.contrast + self.brightness;
+        let i = i * self.saturation * self.contrast;
+        let q = q * self.saturation * self.contrast;
+
+        let channel = |value: f32| {
+            let value = value.clamp(0.0, 1.0).powf(2.2 / self.gamma);
+            (value * 255.0).round() as u32
+        };
+        let r = channel(y + 0.956 * i + 0.621 * q);
+        let g = channel(y - 0.272 * i - 0.647 * q);
+        let b = channel(y - 1.106 * i + 1.703 * q);
+        r << 16 | g << 8 | b
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{with_emphasis, NtscPalette};
+
+    #[test]
+    fn emphasis_darkens_the_other_channels() {
+        let mut base = [0; 64];
+        base[0x20] = 0xc8c8c8;
+        let colors = with_emphasis(&base);
+
+        assert_eq!(colors.len(), 512);
+        assert_eq!(colors[0x20], 0xc8c8c8);
+        // red emphasis keeps red and darkens green and blue
+        assert_eq!(colors[1 << 6 | 0x20], 0xc8a3a3);
+        // all three bits darken everything twice
+        assert_eq!(colors[7 << 6 | 0x20], 0x858585);
+    }
+
+    #[test]
+    fn ntsc_palette_has_neutral_greys() {
+        let colors = NtscPalette::default().generate();
+
+        assert_eq!(colors.len(), 512);
+        assert_eq!(colors[0x0f], 0x000000);
+        assert_eq!(colors[0x20], 0xffffff);
+        // the grey column has no chroma
+        for color in [colors[0x00], colors[0x10], colors[0x2d]] {
+            let [_, r, g, b] = color.to_be_bytes();
+            assert!(r == g && g == b, "{color:06x} is not grey");
+        }
+        // $16 is a red
+        let [_, r, g, b] = colors[0x16].to_be_bytes();
+        assert!(r > g && r > b, "{:06x} is not red", colors[0x16]);
+    }
+}
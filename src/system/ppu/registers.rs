@@ -41,6 +41,15 @@ impl Default for Mask {
     }
 }
 
+impl Mask {
+    /// The emphasis bits as a 3-bit value, red in bit 0
+    pub fn emphasis(&self) -> usize {
+        (if self.emphasis_red    {0b001} else {0})
+        | if self.emphasis_green {0b010} else {0}
+        | if self.emphasis_blue  {0b100} else {0}
+    }
+}
+
 impl From<u8> for Mask {
     fn from(value: u8) -> Self {
         Self {
@@ -81,6 +90,12 @@ impl Default for Control {
     }
 }
 
+impl Control {
+    pub fn sprite_height(&self) -> u16 {
+        if self.tall_sprites {16} else {8}
+    }
+}
+
 impl fmt::Debug for Control {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Control")
@@ -130,6 +145,7 @@ impl From<u8> for Control {
 }
 
 pub struct Status {
+    pub sprite_overflow: bool,
     pub sprite_zero_hit: bool,
     pub vertical_blank: bool,
 }
@@ -137,6 +153,7 @@ pub struct Status {
 impl Default for Status {
     fn default() -> Self {
         Self { 
+            sprite_overflow: false,
             sprite_zero_hit: false,
             vertical_blank: false,
         }
@@ -146,6 +163,7 @@ impl Default for Status {
 impl From<u8> for Status {
     fn from(value: u8) -> Self {
         Self {
+            sprite_overflow: value & 0b0010_0000 != 0,
             sprite_zero_hit: value & 0b0100_0000 != 0,
             vertical_blank:  value & 0b1000_0000 != 0,
         }
@@ -155,6 +173,7 @@ impl From<u8> for Status {
 impl From<&Status> for u8 {
     fn from(value: &Status) -> Self {
         0
+        | if value.sprite_overflow {0b0010_0000} else {0}
         | if value.sprite_zero_hit {0b0100_0000} else {0}
         | if value.vertical_blank  {0b1000_0000} else {0}
     }
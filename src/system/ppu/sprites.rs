@@ -0,0 +1,174 @@
+use crate::system::System;
+
+use super::read_vram;
+
+
+/// Sprites the hardware can show on a single line
+const LINE_LIMIT: usize = 8;
+
+/// Fills secondary OAM with the sprites on the next line, which is what the
+/// PPU does over dots 65 to 256 of the current one.
+pub(super) fn evaluate(sys: &mut System, row: u16) {
+    let height = sys.ppu.control.sprite_height();
+    let in_range = |y: u8| row.wrapping_sub(y as u16) < height;
+
+    sys.ppu.secondary_oam.clear();
+
+    for n in 0..64 {
+        if sys.ppu.secondary_oam.len() == LINE_LIMIT {
+            scan_overflow(sys, n, in_range);
+            if sys.opts.sprite_limit {
+                break
+            }
+        }
+        let base = n * 4;
+        if in_range(sys.oam[base]) {
+            let mut sprite = [0; 4];
+            sprite.copy_from_slice(&sys.oam[base..base + 4]);
+            sys.ppu.secondary_oam.push((n as u8, sprite));
+        }
+    }
+}
+
+/// Looks for a ninth sprite once secondary OAM is full. The hardware
+/// increments the byte offset along with the sprite index here, so it reads
+/// tile, attribute and X bytes as Y coordinates.
+fn scan_overflow(sys: &mut System, start: usize, in_range: impl Fn(u8) -> bool) {
+    let mut m = 0;
+    for n in start..64 {
+        if in_range(sys.oam[n * 4 + m]) {
+            sys.ppu.status.sprite_overflow = true;
+            return
+        }
+        m = (m + 1) % 4;
+    }
+}
+
+/// Fetches the pattern data of the sprites in secondary OAM during dots 257
+/// to 320, eight dots per sprite. Empty slots fetch tile $FF.
+pub(super) fn fetch(sys: &mut System, col: u16, row: u16) -> anyhow::Result<()> {
+    if col == 257 {
+        sys.ppu.sprite_outputs.clear();
+    }
+    sys.ppu.oam_addr = 0;
+
+    let slot = (col - 257) as usize / 8;
+    let phase = (col - 257) % 8;
+
+    // the nametable fetches are thrown away, but the mapper still sees them
+    if phase == 0 || phase == 2 {
+        let v = sys.ppu.v;
+        read_vram(sys, 0x2000 | (v & 0x0fff))?;
+    }
+
+    match phase {
+        4 => {
+            let addr = pattern_addr(sys, slot, row);
+            let upper_sliver = read_vram(sys, addr)?;
+            if let Some(&(index, [y_pos, tile_index, attrs, x_pos])) = sys.ppu.secondary_oam.get(slot) {
+                sys.ppu.sprite_outputs.push([y_pos, tile_index, attrs, x_pos, index, upper_sliver, 0]);
+            }
+        }
+        6 => {
+            let addr = pattern_addr(sys, slot, row) + 8;
+            let lower_sliver = read_vram(sys, addr)?;
+            if let Some(output) = sys.ppu.sprite_outputs.get_mut(slot) {
+                output[6] = lower_sliver;
+            }
+        }
+        7 if slot == LINE_LIMIT - 1 => {
+            // past the hardware limit the remaining sprites are fetched all at once
+            for slot in LINE_LIMIT..sys.ppu.secondary_oam.len() {
+                let (index, [y_pos, tile_index, attrs, x_pos]) = sys.ppu.secondary_oam[slot];
+                let addr = pattern_addr(sys, slot, row);
+                let upper_sliver = read_vram(sys, addr)?;
+                let lower_sliver = read_vram(sys, addr + 8)?;
+                sys.ppu.sprite_outputs.push([y_pos, tile_index, attrs, x_pos, index, upper_sliver, lower_sliver]);
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// Address of the upper pattern plane for the sprite in a secondary OAM
+/// slot on the next line, empty slots use tile $FF
+fn pattern_addr(sys: &System, slot: usize, row: u16) -> u16 {
+    let (_, [y_pos, tile_index, attrs, _]) = sys.ppu.secondary_oam.get(slot)
+        .copied()
+        .unwrap_or((0xff, [0xff; 4]));
+
+    let height = sys.ppu.control.sprite_height();
+    let flip_v = attrs & 0b1000_0000 != 0;
+    let nsy = row.wrapping_sub(y_pos as u16) % height;
+    let sprite_y = if flip_v {height - 1 - nsy} else {nsy};
+
+    let base_addr = if sys.ppu.control.tall_sprites {
+        // bit 0 picks the pattern table, the bottom half uses the next tile
+        let table = (tile_index as u16 & 1) * 0x1000;
+        let tile = (tile_index & 0xfe) as u16 + sprite_y / 8;
+        table + (tile << 4)
+    } else {
+        ((tile_index as u16) << 4) + sys.ppu.control.pattern_base_fg
+    };
+    base_addr + sprite_y % 8
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::system::{System, options::Options};
+
+    fn system_with_sprites(sprite_limit: bool, count: usize) -> System {
+        let mut sys = System::new(Options { sprite_limit, ..Default::default() }).unwrap();
+        sys.oam.fill(0xff);
+        for n in 0..count {
+            sys.oam[n * 4..n * 4 + 4].copy_from_slice(&[20, 0, 0, n as u8 * 8]);
+        }
+        sys
+    }
+
+    #[test]
+    fn evaluation_stops_at_eight_sprites() {
+        let mut sys = system_with_sprites(true, 9);
+        super::evaluate(&mut sys, 22);
+        assert_eq!(sys.ppu.secondary_oam.len(), 8);
+        assert!(sys.ppu.status.sprite_overflow);
+
+        let mut sys = system_with_sprites(true, 8);
+        super::evaluate(&mut sys, 22);
+        assert_eq!(sys.ppu.secondary_oam.len(), 8);
+        assert!(!sys.ppu.status.sprite_overflow);
+    }
+
+    #[test]
+    fn overflow_scan_reads_the_wrong_bytes() {
+        // The ninth sprite is out of range but the scan reads the tile byte
+        // of the tenth sprite as its Y coordinate.
+        let mut sys = system_with_sprites(true, 8);
+        sys.oam[32..40].copy_from_slice(&[0xff, 0, 0, 0, 0xff, 21, 0, 0]);
+        super::evaluate(&mut sys, 22);
+        assert!(sys.ppu.status.sprite_overflow);
+    }
+
+    #[test]
+    fn tall_sprites_cover_sixteen_lines() {
+        let mut sys = system_with_sprites(true, 1);
+        super::evaluate(&mut sys, 35);
+        assert!(sys.ppu.secondary_oam.is_empty());
+
+        sys.ppu.control = 0b0010_0000.into();
+        super::evaluate(&mut sys, 35);
+        assert_eq!(sys.ppu.secondary_oam.len(), 1);
+        super::evaluate(&mut sys, 36);
+        assert!(sys.ppu.secondary_oam.is_empty());
+    }
+
+    #[test]
+    fn disabling_the_limit_keeps_every_sprite() {
+        let mut sys = system_with_sprites(false, 12);
+        super::evaluate(&mut sys, 22);
+        assert_eq!(sys.ppu.secondary_oam.len(), 12);
+        assert!(sys.ppu.status.sprite_overflow);
+    }
+}
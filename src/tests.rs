@@ -4,7 +4,7 @@ use std::{fs, io::{self, Write as IOWrite, BufRead}};
 
 type ParseIntResult<T> = std::result::Result<T, std::num::ParseIntError>;
 
-use crate::system::{self, cpu, execution_state::ExecutionState, addr::Addr, ppu, options::Options};
+use crate::system::{self, cpu, execution_state::ExecutionState, addr::Addr, options::Options};
 
 #[test]
 fn matches_nestest() {
@@ -66,6 +66,8 @@ fn run_with_expect_log(cart_file: &str, log_file: &str, start_pc: u16, steps: us
         
 
         let cpu = system.cpu.clone();
+        let cycles = system.cycles;
+        let ppu = (system.ppu.scan_row, system.ppu.scan_line);
         let (op, am) = cpu::load(&mut system)?;
 
         let byte_count = am.bytes() + 1;
@@ -75,8 +77,8 @@ fn run_with_expect_log(cart_file: &str, log_file: &str, start_pc: u16, steps: us
                 cpu, 
                 pc_bytes, 
                 am: am.clone(), 
-                cycles: system.cycles,
-                ppu: (system.ppu.scan_row, system.ppu.scan_line)
+                cycles,
+                ppu,
         };
 
         let actual_log = actual.to_string();
@@ -132,12 +134,7 @@ fn run_with_expect_log(cart_file: &str, log_file: &str, start_pc: u16, steps: us
         compare_states(expected, actual)?;
         
 
-        let cpu_cycles = op.execute(&mut system, &am)?;
-        let ppu_cycles = cpu_cycles * 3;
-
-        for _ in 0..ppu_cycles {
-            ppu::tick(&mut system)?;
-        }
+        op.execute(&mut system, &am)?;
 
         eprintln!();
 